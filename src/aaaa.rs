@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use tracing::warn;
+use trust_dns_client::op::ResponseCode;
+
+// AAAA failures in a row, each for a name whose A query succeeded, before AAAA is given up on.
+const FAILURE_THRESHOLD: usize = 20;
+
+/// Notices resolvers or middleboxes that break AAAA queries while A works, and turns AAAA off
+/// for the rest of the run so every found name doesn't pay a REFUSED or a timeout for it.
+#[derive(Debug)]
+pub struct AaaaHealth {
+    enabled: AtomicBool,
+    consecutive_failures: AtomicUsize,
+    /// Never give up on AAAA, for `--keep-aaaa`.
+    keep: bool,
+}
+
+impl AaaaHealth {
+    pub fn new(keep: bool) -> Self {
+        AaaaHealth { enabled: AtomicBool::new(true), consecutive_failures: AtomicUsize::new(0), keep }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Records the outcome of an AAAA query for a name that resolved over A. Any answer, even
+    /// NXDOMAIN, shows the AAAA path works; REFUSED, SERVFAIL and timeouts count against it.
+    pub fn record(&self, rcode: Option<ResponseCode>) {
+        if !matches!(rcode, None | Some(ResponseCode::Refused) | Some(ResponseCode::ServFail)) {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= FAILURE_THRESHOLD && !self.keep && self.enabled.swap(false, Ordering::Relaxed) {
+            warn!(
+                "AAAA queries failed {} times in a row for names that resolve over A, skipping AAAA for the rest of the scan (use --keep-aaaa to keep querying)",
+                failures
+            );
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use trust_dns_client::rr::RecordType;
+
+use crate::resolvers::{ResolverPool, RetryPolicy, Transport, DNS_PORT};
+use crate::QueryOptions;
+
+/// What the authoritative server answered for a name the resolvers answered differently.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthoritativeMismatch {
+    pub server: SocketAddr,
+    pub rcode: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cnames: Vec<String>,
+}
+
+/// One of the target zone's nameservers, queried directly so its answers can be compared with
+/// the recursive resolvers'. Differences point at split-horizon DNS or stale caches.
+pub struct Authoritative {
+    server: SocketAddr,
+    pool: ResolverPool,
+}
+
+impl Authoritative {
+    /// Looks up the NS records of `target` through `pool`, walking up to parent domains until
+    /// one has them, and connects to the first nameserver that resolves.
+    pub async fn discover(pool: &ResolverPool, target: &str, options: QueryOptions, transport: Transport, timeout: Duration, retry: RetryPolicy) -> Option<Self> {
+        let ns_options = QueryOptions { record_type: RecordType::NS, ..options };
+        let mut zone = target;
+
+        let nameservers = loop {
+            let nameservers = pool.resolve(zone, ns_options).await.records;
+            if !nameservers.is_empty() {
+                break nameservers;
+            }
+            match zone.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => zone = parent,
+                _ => {
+                    warn!("Found no NS records for {} or its parents, not comparing with the authoritative server", target);
+                    return None;
+                }
+            }
+        };
+
+        let address_options = QueryOptions { record_type: RecordType::A, ..options };
+        for nameserver in &nameservers {
+            let nameserver = nameserver.trim_end_matches('.');
+            if let Some(&ip) = pool.resolve(nameserver, address_options).await.addresses.first() {
+                let server = SocketAddr::new(ip, DNS_PORT);
+                let pool = ResolverPool::connect(&[server], transport, timeout, retry, 0).await.ok()?;
+                info!("Comparing answers with {} ({}), authoritative for {}", nameserver, server, zone);
+
+                return Some(Authoritative { server, pool });
+            }
+        }
+
+        warn!("None of the nameservers {:?} of {} resolved, not comparing with the authoritative server", nameservers, zone);
+        None
+    }
+
+    /// Queries the authoritative server for `hostname` and returns its answer when it disagrees
+    /// with `addresses` and `cnames` from the resolvers. An answer that only points outside the
+    /// zone through a CNAME is compared on its first CNAME target alone. No response is no mismatch.
+    pub async fn compare(&self, hostname: &str, addresses: &[IpAddr], cnames: &[String], options: QueryOptions) -> Option<AuthoritativeMismatch> {
+        let options = QueryOptions { recursion_desired: false, ..options };
+        let answer = self.pool.resolve(hostname, options).await;
+        answer.rcode?;
+
+        let normalize = |name: &String| name.trim_end_matches('.').to_lowercase();
+        let matches = if answer.addresses.is_empty() && !answer.cnames.is_empty() {
+            answer.cnames.first().map(normalize) == cnames.first().map(normalize)
+        } else {
+            answer.addresses.iter().collect::<BTreeSet<_>>() == addresses.iter().collect::<BTreeSet<_>>()
+        };
+        if matches {
+            return None;
+        }
+
+        Some(AuthoritativeMismatch {
+            server: self.server,
+            rcode: answer.rcode_name(),
+            addresses: answer.addresses,
+            cnames: answer.cnames,
+        })
+    }
+}
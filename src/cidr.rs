@@ -0,0 +1,116 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+/// An IPv4 or IPv6 network such as `104.16.0.0/13`. A bare address is a single-host network.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of addresses in the network, saturating for an IPv6 `/0`.
+    pub fn size(&self) -> u128 {
+        1u128.checked_shl(u32::from(self.max_prefix() - self.prefix)).unwrap_or(u128::MAX)
+    }
+
+    /// Every address of the network in ascending order, generated as iterated so even a huge
+    /// network costs no memory up front.
+    pub fn hosts(&self) -> impl Iterator<Item = IpAddr> {
+        let mask = u128::MAX.checked_shl(u32::from(self.max_prefix() - self.prefix)).unwrap_or(0);
+        let (first, ipv4) = match self.network {
+            IpAddr::V4(network) => (u128::from(u32::from(network)) & mask, true),
+            IpAddr::V6(network) => (u128::from(network) & mask, false),
+        };
+
+        (0..self.size()).map(move |offset| {
+            let bits = first.wrapping_add(offset);
+            if ipv4 {
+                IpAddr::V4(Ipv4Addr::from(bits as u32))
+            } else {
+                IpAddr::V6(Ipv6Addr::from(bits))
+            }
+        })
+    }
+
+    fn max_prefix(&self) -> u8 {
+        if self.network.is_ipv4() { 32 } else { 128 }
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(ip: IpAddr) -> Self {
+        let prefix = if ip.is_ipv4() { 32 } else { 128 };
+
+        Cidr { network: ip, prefix }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = address.parse().with_context(|| format!("Invalid network address in {}", s))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().with_context(|| format!("Invalid prefix length in {}", s))?,
+            None => max_prefix,
+        };
+
+        if prefix > max_prefix {
+            bail!("Prefix length of {} is longer than {} bits", s, max_prefix);
+        }
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix == self.max_prefix() {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+impl fmt::Debug for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Addresses that never route on the public internet: private, loopback, link-local, shared
+/// (100.64.0.0/10) and unique local IPv6. A public name resolving to one leaks internal layout.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback() || ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfe80
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::resolvers::ResolverPool;
+use crate::QueryOptions;
+
+/// One resolver's answer for a name the resolvers disagree on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolverAnswer {
+    pub resolver: SocketAddr,
+    pub rcode: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Asks `count` resolvers about `hostname` at once and returns every answer when they don't
+/// all return the same addresses. Resolvers that didn't respond have no say, so fewer than two
+/// responses is no disagreement.
+pub async fn check(pool: &ResolverPool, hostname: &str, options: QueryOptions, count: usize) -> Option<Vec<ResolverAnswer>> {
+    let answers: Vec<ResolverAnswer> = pool
+        .resolve_each(hostname, options, count)
+        .await
+        .into_iter()
+        .filter(|resolution| resolution.rcode.is_some())
+        .filter_map(|resolution| {
+            Some(ResolverAnswer {
+                resolver: resolution.resolver?,
+                rcode: resolution.rcode_name(),
+                addresses: resolution.addresses,
+            })
+        })
+        .collect();
+
+    let mut signatures = answers.iter().map(|answer| answer.addresses.iter().collect::<BTreeSet<_>>());
+    let first = signatures.next()?;
+    if answers.len() < 2 || signatures.all(|signature| signature == first) {
+        return None;
+    }
+
+    Some(answers)
+}
@@ -0,0 +1,46 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use tracing::info;
+
+/// Names from `--deny-file` that are never queried, whether they come from the wordlist or
+/// were discovered during the scan.
+#[derive(Debug, Clone, Default)]
+pub struct DenyList {
+    patterns: Vec<Regex>,
+}
+
+impl DenyList {
+    /// Reads one label or name per line, where `*` matches any run of characters. Blank lines
+    /// and `#` comments are skipped.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Couldn't read deny file {}", path))?;
+        let patterns = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim().trim_end_matches('.').to_lowercase())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let pattern = line.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+                Regex::new(&format!("^{}$", pattern)).with_context(|| format!("Invalid deny pattern {:?} in {}", line, path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        info!("Loaded {} deny patterns from {}", patterns.len(), path);
+
+        Ok(DenyList { patterns })
+    }
+
+    /// Whether a pattern matches `hostname` as a whole, its part under `target`, or any one of
+    /// its labels, so `admin` denies `admin.example.com` and `admin.dev.example.com` alike.
+    pub fn denies(&self, hostname: &str, target: &str) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let hostname = hostname.trim_end_matches('.').to_lowercase();
+        let relative = hostname.strip_suffix(&format!(".{}", target)).unwrap_or(&hostname);
+
+        self.patterns.iter().any(|pattern| {
+            pattern.is_match(&hostname) || pattern.is_match(relative) || relative.split('.').any(|label| pattern.is_match(label))
+        })
+    }
+}
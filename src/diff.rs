@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::output::Compression;
+use crate::RootDomain;
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScanDiff {
+    added_subdomains: Vec<String>,
+    removed_subdomains: Vec<String>,
+    changed_addresses: Vec<AddressChange>,
+    opened_ports: Vec<PortChange>,
+    closed_ports: Vec<PortChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct AddressChange {
+    name: String,
+    added: Vec<IpAddr>,
+    removed: Vec<IpAddr>,
+}
+
+#[derive(Debug, Serialize)]
+struct PortChange {
+    name: String,
+    ip: IpAddr,
+    port: u16,
+}
+
+type Hosts = BTreeMap<String, BTreeMap<IpAddr, BTreeSet<u16>>>;
+
+/// Reads a JSON result file, decompressing files written with `--compress`. The compression is
+/// recognized from the file's first bytes, so a compressed file without a `.gz` or `.zst` name
+/// reads too.
+pub fn load(path: &str) -> Result<RootDomain> {
+    let mut file = BufReader::new(fs::File::open(path).with_context(|| format!("Couldn't open result file {}", path))?);
+    let magic = file.fill_buf().with_context(|| format!("Couldn't read result file {}", path))?;
+    let reader: Box<dyn Read> = match Compression::from_magic(magic).or_else(|| Compression::from_path(path)) {
+        Some(Compression::Gzip) => Box::new(flate2::read::GzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::with_buffer(file)?),
+        None => Box::new(file),
+    };
+
+    serde_json::from_reader(reader).with_context(|| format!("Couldn't parse result file {}", path))
+}
+
+/// Every name of a result, root included, with the open ports of each of its addresses.
+fn hosts(root_domain: &RootDomain) -> Hosts {
+    std::iter::once((&root_domain.name, &root_domain.addresses))
+        .chain(root_domain.subdomains.iter().map(|subdomain| (&subdomain.name, &subdomain.addresses)))
+        .map(|(name, addresses)| {
+            let addresses = addresses
+                .iter()
+                .map(|address| (address.ip, address.ports.iter().filter(|port| port.is_open()).map(|port| port.port).collect()))
+                .collect();
+
+            (name.clone(), addresses)
+        })
+        .collect()
+}
+
+/// Drops everything `baseline` already had from `root_domain`: subdomains it knew, and the
+/// known addresses of subdomains that gained new ones. The root entry is kept as is.
+pub fn only_new(root_domain: &mut RootDomain, baseline: &RootDomain) {
+    let known = hosts(baseline);
+
+    root_domain.subdomains.retain_mut(|subdomain| match known.get(&subdomain.name) {
+        Some(addresses) => {
+            subdomain.addresses.retain(|address| !addresses.contains_key(&address.ip));
+            !subdomain.addresses.is_empty()
+        }
+        None => true,
+    });
+    if root_domain.hosts_by_ip.is_some() {
+        root_domain.hosts_by_ip = Some(root_domain.hosts_by_ip());
+    }
+    root_domain.summarize();
+}
+
+pub fn diff(old: &RootDomain, new: &RootDomain) -> ScanDiff {
+    let old = hosts(old);
+    let new = hosts(new);
+    let mut diff = ScanDiff {
+        removed_subdomains: old.keys().filter(|name| !new.contains_key(*name)).cloned().collect(),
+        ..Default::default()
+    };
+
+    for (name, new_addresses) in &new {
+        let Some(old_addresses) = old.get(name) else {
+            diff.added_subdomains.push(name.clone());
+            continue;
+        };
+
+        let added: Vec<IpAddr> = new_addresses.keys().filter(|ip| !old_addresses.contains_key(*ip)).copied().collect();
+        let removed: Vec<IpAddr> = old_addresses.keys().filter(|ip| !new_addresses.contains_key(*ip)).copied().collect();
+
+        if !added.is_empty() || !removed.is_empty() {
+            diff.changed_addresses.push(AddressChange { name: name.clone(), added, removed });
+        }
+
+        // Ports are only compared on addresses present in both scans; the rest is an address change.
+        for (ip, new_ports) in new_addresses {
+            let Some(old_ports) = old_addresses.get(ip) else {
+                continue;
+            };
+
+            diff.opened_ports.extend(new_ports.difference(old_ports).map(|&port| PortChange { name: name.clone(), ip: *ip, port }));
+            diff.closed_ports.extend(old_ports.difference(new_ports).map(|&port| PortChange { name: name.clone(), ip: *ip, port }));
+        }
+    }
+
+    diff
+}
+
+impl ScanDiff {
+    pub fn print_summary(&self) {
+        println!("{} added, {} removed subdomains", self.added_subdomains.len(), self.removed_subdomains.len());
+
+        for name in &self.added_subdomains {
+            println!("+ {}", name);
+        }
+
+        for name in &self.removed_subdomains {
+            println!("- {}", name);
+        }
+
+        for change in &self.changed_addresses {
+            println!("~ {}: +{:?} -{:?}", change.name, change.added, change.removed);
+        }
+
+        for change in &self.opened_ports {
+            println!("+ {} {}:{}", change.name, change.ip, change.port);
+        }
+
+        for change in &self.closed_ports {
+            println!("- {} {}:{}", change.name, change.ip, change.port);
+        }
+    }
+}
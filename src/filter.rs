@@ -0,0 +1,33 @@
+use regex::Regex;
+
+use crate::Address;
+
+/// Narrows found names down to the addresses matching `--ip-regex` and `--ptr-regex`.
+#[derive(Debug, Clone, Default)]
+pub struct AnswerFilter {
+    ip: Option<Regex>,
+    ptr: Option<Regex>,
+}
+
+impl AnswerFilter {
+    pub fn new(ip: Option<Regex>, ptr: Option<Regex>) -> Self {
+        AnswerFilter { ip, ptr }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ip.is_none() && self.ptr.is_none()
+    }
+
+    /// Drops the addresses that don't match: the IP pattern against the address as text, the
+    /// PTR pattern against any of its PTR names. Returns whether any address is left.
+    pub fn retain(&self, addresses: &mut Vec<Address>) -> bool {
+        addresses.retain(|address| {
+            let ip_matches = self.ip.as_ref().is_none_or(|ip| ip.is_match(&address.ip.to_string()));
+            let ptr_matches = self.ptr.as_ref().is_none_or(|ptr| address.ptr.iter().any(|name| ptr.is_match(name)));
+
+            ip_matches && ptr_matches
+        });
+
+        !addresses.is_empty()
+    }
+}
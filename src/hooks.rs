@@ -0,0 +1,73 @@
+use std::process::Stdio;
+use std::sync::Arc;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::Subdomain;
+
+/// Runs a user command for every discovered subdomain, `--on-found`.
+pub struct FoundHook {
+    command: String,
+    spawns: Arc<Semaphore>,
+    max_spawns: u32,
+}
+
+impl FoundHook {
+    pub fn new(command: String, max_spawns: u16) -> Self {
+        FoundHook { command, spawns: Arc::new(Semaphore::new(max_spawns.into())), max_spawns: max_spawns.into() }
+    }
+
+    /// Starts the command for `subdomain` in the background once fewer than `max_spawns` are
+    /// running, waiting until then, so findings pile up in the caller rather than as tasks.
+    pub async fn start(self: &Arc<Self>, subdomain: Subdomain) {
+        let permit = Arc::clone(&self.spawns).acquire_owned().await.expect("hook semaphore closed");
+        let hook = Arc::clone(self);
+
+        tokio::spawn(async move {
+            hook.run(subdomain).await;
+            drop(permit);
+        });
+    }
+
+    /// Waits for every started command to finish.
+    pub async fn wait(&self) {
+        let _all = self.spawns.acquire_many(self.max_spawns).await.expect("hook semaphore closed");
+    }
+
+    /// Runs the command through `sh -c` with the name and each IP as positional arguments and the
+    /// subdomain as JSON on stdin. Failures are logged and never abort the scan.
+    async fn run(&self, subdomain: Subdomain) {
+        let json = serde_json::to_string(&subdomain).expect("Couldn't serialize subdomain");
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("port-scanner")
+            .arg(&subdomain.name)
+            .args(subdomain.addresses.iter().map(|address| address.ip.to_string()))
+            .stdin(Stdio::piped())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                warn!("Couldn't run --on-found command for {}: {}", subdomain.name, err);
+                return;
+            }
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // The command may exit without reading stdin, so a broken pipe here is not an error.
+            let _ = stdin.write_all(json.as_bytes()).await;
+        }
+
+        match child.wait().await {
+            Ok(status) if status.success() => debug!("--on-found command succeeded for {}", subdomain.name),
+            Ok(status) => warn!("--on-found command for {} exited with {}", subdomain.name, status),
+            Err(err) => warn!("--on-found command for {} failed: {}", subdomain.name, err),
+        }
+    }
+}
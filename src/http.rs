@@ -0,0 +1,182 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsConnector;
+use tracing::debug;
+use url::{Host, Url};
+
+use crate::resolvers::ResolverPool;
+use crate::{interface, tls, QueryOptions};
+
+pub const HTTP_PORT: u16 = 80;
+
+// Redirects followed from one port before giving up, which also ends redirect loops.
+const MAX_REDIRECTS: usize = 10;
+// Only the status line and headers are needed, so a response is never read past this.
+const MAX_HEAD_LEN: usize = 16 * 1024;
+
+/// Follows the HTTP redirects of the sites on 80 and 443, recording every hop.
+pub struct RedirectFollower {
+    connector: TlsConnector,
+}
+
+impl RedirectFollower {
+    pub fn new() -> Self {
+        RedirectFollower { connector: tls::any_certificate_connector() }
+    }
+
+    /// Requests `/` from `hostname` at `ip`:`port` and follows `Location` headers, returning
+    /// each URL redirected to in order. Hops to another hostname are resolved with `pool`.
+    /// The chain stops at `MAX_REDIRECTS`, on a URL seen before, or at the first failure.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn chain(&self, pool: &ResolverPool, options: QueryOptions, hostname: &str, ip: IpAddr, port: u16, request_timeout: Duration, sockets: &Semaphore) -> Vec<String> {
+        let scheme = if port == tls::HTTPS_PORT { "https" } else { "http" };
+        let Ok(mut url) = Url::parse(&format!("{}://{}:{}/", scheme, hostname, port)) else {
+            return vec![];
+        };
+        let mut ip = ip;
+        let mut chain: Vec<String> = vec![];
+
+        while chain.len() < MAX_REDIRECTS {
+            let location = {
+                let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                match timeout(request_timeout, self.location(&url, ip)).await {
+                    Ok(Ok(location)) => location,
+                    Ok(Err(err)) => {
+                        debug!("HTTP request to {} ({}) failed: {:#}", url, ip, err);
+                        None
+                    }
+                    Err(_) => {
+                        debug!("HTTP request to {} ({}) timed out", url, ip);
+                        None
+                    }
+                }
+            };
+            let Some(next) = location.and_then(|location| url.join(&location).ok()) else {
+                break;
+            };
+            if chain.contains(&next.to_string()) {
+                debug!("Redirect loop at {} from {}", next, hostname);
+                break;
+            }
+            chain.push(next.to_string());
+
+            if !matches!(next.scheme(), "http" | "https") {
+                break;
+            }
+            if next.host_str() != url.host_str() {
+                let Some(host) = next.host_str() else {
+                    break;
+                };
+                let resolution = {
+                    let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                    pool.resolve(host, options).await
+                };
+                let Some(&address) = resolution.addresses.first() else {
+                    break;
+                };
+                ip = address;
+            }
+            url = next;
+        }
+
+        chain
+    }
+
+    /// Sends one GET for `url` to `ip` and returns the `Location` of a redirect response.
+    async fn location(&self, url: &Url, ip: IpAddr) -> Result<Option<String>> {
+        let port = url.port_or_known_default().context("URL has no port")?;
+        let stream = interface::connect_tcp(SocketAddr::new(ip, port)).await?;
+
+        let head = match url.scheme() {
+            "https" => {
+                let stream = self.connector.connect(server_name(url)?, stream).await?;
+                send(stream, "GET", url, &[("Accept", "*/*")], &[]).await?
+            }
+            "http" => send(stream, "GET", url, &[("Accept", "*/*")], &[]).await?,
+            scheme => bail!("Unsupported scheme {}", scheme),
+        };
+        if !(300..400).contains(&head.status) {
+            return Ok(None);
+        }
+
+        Ok(head.header("location").map(str::to_string))
+    }
+}
+
+/// TLS server name of `url`'s host. IPv6 hosts are bracketed in URLs but not in server names.
+pub fn server_name(url: &Url) -> Result<ServerName> {
+    match url.host() {
+        Some(Host::Domain(domain)) => Ok(ServerName::try_from(domain)?),
+        Some(Host::Ipv4(ip)) => Ok(ServerName::IpAddress(ip.into())),
+        Some(Host::Ipv6(ip)) => Ok(ServerName::IpAddress(ip.into())),
+        None => bail!("URL {} has no host", url),
+    }
+}
+
+/// Status and headers of a response; the body is never read.
+pub struct ResponseHead {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl ResponseHead {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends one HTTP/1.1 `method` request for `url` with `headers` and, if not empty, `body`, and
+/// reads the response head.
+pub async fn send<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, method: &str, url: &Url, headers: &[(&str, &str)], body: &[u8]) -> Result<ResponseHead> {
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => bail!("URL has no host"),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: port-scanner/{}\r\n", method, path, host, env!("CARGO_PKG_VERSION"));
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !body.is_empty() {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let mut head = vec![];
+    let mut buf = [0; 4096];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_HEAD_LEN {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .context("Malformed HTTP status line")?;
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    Ok(ResponseHead { status, headers })
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+
+use anyhow::{bail, Context as _, Result};
+use async_trait::async_trait;
+use futures::io::{AsyncRead, AsyncWrite};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use trust_dns_client::proto::iocompat::AsyncIoTokioAsStd;
+use trust_dns_client::proto::tcp::{Connect, DnsTcpStream};
+use trust_dns_client::proto::udp::UdpSocket as DnsUdpSocket;
+use trust_dns_client::proto::TokioTime;
+
+// Global because trust-dns creates its sockets through static trait functions, which can't be
+// handed the interface any other way.
+static INTERFACE: OnceLock<String> = OnceLock::new();
+
+/// Pins every socket opened after this call to `name` with SO_BINDTODEVICE. Linux only, and
+/// needs CAP_NET_RAW on kernels before 5.7.
+pub fn bind_to(name: &str) -> Result<()> {
+    if !cfg!(any(target_os = "linux", target_os = "android", target_os = "fuchsia")) {
+        bail!("--interface relies on SO_BINDTODEVICE, which is only available on Linux");
+    }
+
+    // Fail now rather than on the first query if the interface is missing or not permitted.
+    let probe = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    bind_device(&probe, name).with_context(|| format!("Couldn't bind to interface {}", name))?;
+
+    INTERFACE.set(name.to_string()).ok();
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "fuchsia"))]
+fn bind_device(socket: &Socket, name: &str) -> io::Result<()> {
+    socket.bind_device(Some(name.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "fuchsia")))]
+fn bind_device(_socket: &Socket, _name: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "SO_BINDTODEVICE is Linux only"))
+}
+
+/// Binds `socket` to the configured interface, if any.
+pub fn apply(socket: &Socket) -> io::Result<()> {
+    match INTERFACE.get() {
+        Some(name) => bind_device(socket, name),
+        None => Ok(()),
+    }
+}
+
+/// Connects a TCP stream to `addr` through the configured interface.
+pub async fn connect_tcp(addr: SocketAddr) -> io::Result<TcpStream> {
+    if INTERFACE.get().is_none() {
+        return TcpStream::connect(addr).await;
+    }
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    apply(&socket)?;
+    socket.set_nonblocking(true)?;
+
+    TcpSocket::from_std_stream(socket.into()).connect(addr).await
+}
+
+/// Binds a std UDP socket to `addr` on the configured interface.
+pub fn bind_std_udp(addr: SocketAddr) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    apply(&socket)?;
+    socket.bind(&addr.into())?;
+
+    Ok(socket.into())
+}
+
+/// UDP socket for trust-dns clients that honours `--interface`.
+pub struct InterfaceUdpSocket(UdpSocket);
+
+#[async_trait]
+impl DnsUdpSocket for InterfaceUdpSocket {
+    type Time = TokioTime;
+
+    async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let bind_addr: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+
+        Self::bind(bind_addr).await
+    }
+
+    async fn connect_with_bind(_addr: SocketAddr, bind_addr: SocketAddr) -> io::Result<Self> {
+        Self::bind(bind_addr).await
+    }
+
+    async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = bind_std_udp(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(InterfaceUdpSocket(UdpSocket::from_std(socket)?))
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<(usize, SocketAddr)>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        let addr = futures::ready!(self.0.poll_recv_from(cx, &mut buf))?;
+
+        Poll::Ready(Ok((buf.filled().len(), addr)))
+    }
+
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        self.0.poll_send_to(cx, buf, target)
+    }
+}
+
+/// TCP stream for trust-dns clients that honours `--interface`.
+pub struct InterfaceTcpStream(AsyncIoTokioAsStd<TcpStream>);
+
+impl DnsTcpStream for InterfaceTcpStream {
+    type Time = TokioTime;
+}
+
+#[async_trait]
+impl Connect for InterfaceTcpStream {
+    async fn connect_with_bind(addr: SocketAddr, _bind_addr: Option<SocketAddr>) -> io::Result<Self> {
+        connect_tcp(addr).await.map(|stream| InterfaceTcpStream(AsyncIoTokioAsStd(stream)))
+    }
+}
+
+impl AsyncRead for InterfaceTcpStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for InterfaceTcpStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
@@ -0,0 +1,23 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Whether log lines are colored: only when they go to a terminal, and never with `NO_COLOR`
+/// set to a non-empty value (https://no-color.org).
+pub fn use_color(stream: &impl IsTerminal) -> bool {
+    let color = stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    COLOR.store(color, Ordering::Relaxed);
+
+    color
+}
+
+/// Tag that marks a high-signal finding, such as a possible takeover, so it stands out among
+/// routine log lines. Bold magenta on a color terminal, plain `[TAG]` otherwise.
+pub fn finding(tag: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[1;35m[{}]\x1b[0m", tag)
+    } else {
+        format!("[{}]", tag)
+    }
+}
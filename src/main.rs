@@ -1,64 +1,411 @@
+mod aaaa;
+mod authoritative;
+mod cidr;
+mod consensus;
+mod deny;
+mod diff;
+mod filter;
+mod hooks;
+mod http;
+mod interface;
+mod logging;
+mod metadata;
+mod output;
+mod pause;
+mod ports;
+mod profile;
+mod progress;
+mod ramp;
+#[cfg(all(unix, feature = "raw-socket"))]
+mod raw_scan;
+mod resolvers;
+mod seen;
+mod server;
+mod sqlite;
+mod stats;
+mod takeover;
+mod targets;
+mod tls;
+mod tui;
+mod webhook;
+mod wildcard;
+
 use std::{
-    fs, io::prelude::*, net::{IpAddr, SocketAddr}, str::FromStr, sync::Arc
+    collections::{BTreeMap, BTreeSet, HashSet}, fs, io::{prelude::*, IsTerminal}, net::{IpAddr, SocketAddr}, str::FromStr,
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc},
 };
-use std::time::Duration;
-use clap::Parser;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use async_channel::bounded as BoundedChannel;
 use async_channel::unbounded as UnboundedChannel;
 use async_channel::{ Receiver, Sender};
 use futures::future::join_all;
-use trust_dns_client::client::{AsyncClient, ClientHandle};
+use futures::StreamExt;
+use trust_dns_client::client::AsyncClient;
+use trust_dns_client::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_client::proto::xfer::{DnsHandle, DnsRequest, DnsRequestOptions, FirstAnswer};
 use trust_dns_client::rr::{DNSClass, Name, RData, RecordType};
-use trust_dns_client::udp::UdpClientStream;
-use tokio::net::UdpSocket;
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::Serialize;
-use anyhow::Result;
-use tracing::{info, warn};
-use tokio::sync::Mutex;
-use tracing_subscriber;
-
-#[derive(Debug, Clone, Serialize)]
+use tokio::sync::Semaphore;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use rand::Rng;
+use tracing::{debug, error, info, warn, Level};
+use aaaa::AaaaHealth;
+use authoritative::{Authoritative, AuthoritativeMismatch};
+use cidr::Cidr;
+use consensus::ResolverAnswer;
+use deny::DenyList;
+use filter::AnswerFilter;
+use hooks::FoundHook;
+use http::RedirectFollower;
+use metadata::ScanMetadata;
+use output::{Compression, JsonStream, OutputFormat};
+use pause::PauseSwitch;
+use ports::{HostLimits, Port, PortShuffle, PortState, ScanType};
+use profile::{Profile, ProfileDefaults};
+use progress::{Progress, ProgressFile};
+use ramp::RampUp;
+use stats::{LatencyHistogram, LatencyStats};
+use seen::FirstSeen;
+use resolvers::{ResolverPool, ResolverRateLimits, ResolverStrategy, RetryPolicy, RetryStrategy, Transport};
+use takeover::Fingerprints;
+use targets::Target;
+use tls::{CertificateCollector, VirtualHosts};
+use tui::Dashboard;
+use url::Url;
+use webhook::Webhook;
+use wildcard::{WildcardDetector, WildcardSet};
+
+// Names are queued to workers in batches to cut per-item channel overhead on huge wordlists.
+const QUEUE_BATCH_SIZE: usize = 64;
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+// UDP payload size advertised in EDNS, the value trust-dns uses for its own queries.
+const EDNS_PAYLOAD_LEN: u16 = 1232;
+// CNAME hops followed through an answer before giving up, which also stops a CNAME loop.
+const MAX_CNAME_HOPS: usize = 16;
+// Names scanned per chunk with --low-memory when --chunk-size isn't given.
+const LOW_MEMORY_CHUNK_SIZE: u64 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 struct RootDomain {
     name: String,
+    #[serde(default)]
+    subdomain_count: usize,
+    /// Addresses of the target and its subdomains, counting an IP once per name it belongs to.
+    #[serde(default)]
+    total_addresses: usize,
+    #[serde(default)]
+    unique_ip_count: usize,
     addresses: Vec<Address>,
     subdomains: Vec<Subdomain>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hosts_by_ip: Option<Vec<Host>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unresolved: Vec<Unresolved>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    wildcard_signatures: Vec<Vec<IpAddr>>,
+    /// DNS query latency percentiles, kept with `--latency-stats`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    latency: Option<LatencyStats>,
+    /// What each address with 443 open answered for every found name as SNI, with `--sni-vhosts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vhosts: Vec<VirtualHosts>,
+    /// Scan id, tags and run details, set for command line scans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<ScanMetadata>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 struct Subdomain {
     name: String,
+    /// Empty for a name that exists only as a CNAME to a name without addresses, a common
+    /// sign of a dangling record.
     addresses: Vec<Address>,
+    /// CNAME chain from `name` to the final target, in order, so `a -> b -> c` is `[b, c]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cnames: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    takeover: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rcode: Option<String>,
+    #[serde(default)]
+    query_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolver: Option<SocketAddr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    records: Vec<String>,
+    /// Lowest TTL among the answer records, in seconds. Low values often mean a load balancer
+    /// or failover setup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    /// Answer of the zone's nameserver when it differs from the resolvers', with `--compare-authoritative`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    authoritative_mismatch: Option<AuthoritativeMismatch>,
+    /// Answer of each resolver asked with `--consensus`, kept only when they disagree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolver_answers: Option<Vec<ResolverAnswer>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sans: Vec<String>,
+    /// AD bit of the answer with `--dnssec`, i.e. whether the resolver validated it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dnssec_ok: Option<bool>,
+    /// Answers to the `--also-collect` record types, keyed by type.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extra_records: BTreeMap<String, Vec<String>>,
+    /// TXT records at `_acme-challenge.<name>` with `--acme`, left behind by a certificate
+    /// issuance in progress or one that was never cleaned up.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    acme_challenge: Vec<String>,
+    /// Start of the first scan that found this name, kept with `--timestamp-findings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    first_seen: Option<DateTime<Utc>>,
+    /// Start of the latest scan that found this name, kept with `--timestamp-findings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Unresolved {
+    name: String,
+    rcode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query_ms: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 struct Address {
     ip: IpAddr,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<Port>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ptr: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Host {
+    ip: IpAddr,
+    names: Vec<String>,
+}
+
+impl RootDomain {
+    /// Groups every resolved name by address, so each IP appears once with all names pointing at it.
+    fn hosts_by_ip(&self) -> Vec<Host> {
+        let mut hosts: BTreeMap<IpAddr, Vec<String>> = BTreeMap::new();
+        let names = std::iter::once((&self.name, &self.addresses))
+            .chain(self.subdomains.iter().map(|subdomain| (&subdomain.name, &subdomain.addresses)));
+
+        for (name, addresses) in names {
+            for address in addresses {
+                let names = hosts.entry(address.ip).or_default();
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        hosts.into_iter().map(|(ip, names)| Host { ip, names }).collect()
+    }
+
+    /// Fills in the summary counts from the current addresses and subdomains.
+    fn summarize(&mut self) {
+        let addresses = || {
+            self.addresses.iter()
+                .chain(self.subdomains.iter().flat_map(|subdomain| &subdomain.addresses))
+        };
+
+        self.subdomain_count = self.subdomains.len();
+        self.total_addresses = addresses().count();
+        self.unique_ip_count = addresses().map(|address| address.ip).collect::<HashSet<_>>().len();
+    }
+
+    /// Adds the names found by a later scan of the same target, keeping this scan's root entry
+    /// and wildcard signatures. Latency percentiles can't be combined, so the latest scan's are kept.
+    fn merge(&mut self, other: RootDomain) {
+        self.subdomains.extend(other.subdomains);
+        self.unresolved.extend(other.unresolved);
+        self.vhosts.extend(other.vhosts);
+        self.latency = other.latency.or(self.latency.take());
+        if self.hosts_by_ip.is_some() {
+            self.hosts_by_ip = Some(self.hosts_by_ip());
+        }
+        self.summarize();
+    }
 }
 
 #[derive(Parser)]
-#[clap(author, version, about)]
+#[clap(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
-    #[clap(short, long, help = "target domain")]
-    target: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(short, long, env = "PORT_SCANNER_TARGET", required_unless_present_any = ["targets_file", "list_ports", "print_config", "fqdn"], help = "target domain, or an IP address or network such as 10.0.0.0/24 to inspect without a wordlist")]
+    target: Option<String>,
+
+    #[clap(
+    long,
+    conflicts_with = "target",
+    help = "file of host:port or ip:port targets to connect-scan directly, one per line, skipping enumeration; hostnames are resolved once and IPs not at all"
+    )]
+    targets_file: Option<String>,
 
     #[clap(
     short,
     long,
-    default_value = "8.8.8.8:53",
-    help = "default is 8.8.8.8:53"
+    env = "PORT_SCANNER_RESOLVERS",
+    value_delimiter = ',',
+    help = "resolvers to query round-robin, repeatable or comma separated(default is 8.8.8.8:53 unless --resolvers-file is given)"
+    )]
+    dns_resolver: Vec<SocketAddr>,
+
+    #[clap(
+    long,
+    help = "file of resolvers added to --dns-resolver, one ip:port or bare ip (port 53) per line"
+    )]
+    resolvers_file: Option<String>,
+
+    #[clap(
+    long,
+    help = "before scanning, drop resolvers that can't resolve --validation-name or that answer for a random name under it"
+    )]
+    validate_resolvers: bool,
+
+    #[clap(
+    long,
+    default_value = resolvers::DEFAULT_VALIDATION_NAME,
+    help = "name a resolver must resolve to pass --validate-resolvers(default is example.com)"
+    )]
+    validation_name: String,
+
+    #[clap(
+    long,
+    value_parser = parse_rate,
+    help = "maximum queries per second sent to each resolver, enforced separately per resolver across all workers(default is unlimited)"
+    )]
+    resolver_rate: Option<f64>,
+
+    #[clap(
+    long,
+    help = "network interface all DNS and scan sockets are bound to with SO_BINDTODEVICE (Linux only)"
     )]
-    dns_resolver: SocketAddr,
+    interface: Option<String>,
 
     #[clap(
     short,
     long,
-    default_value_t = 1,
-    help = "set concurrency level(default is 1)"
+    value_parser = clap::value_parser!(u8).range(1..),
+    help = "set concurrency level(default is 1, 100 with --fast, 16 with --thorough)"
+    )]
+    concurrency: Option<u8>,
+
+    #[clap(
+    long,
+    alias = "threads",
+    value_parser = clap::value_parser!(u16).range(1..),
+    help = "threads of the async runtime, independent of --concurrency; the scan is IO-bound, so a few usually suffice(default is one per CPU core)"
+    )]
+    worker_threads: Option<u16>,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "milliseconds to wait for a DNS answer, port connect or TLS handshake(default is 1000, 500 with --fast, 3000 with --thorough)"
+    )]
+    timeout_ms: Option<u64>,
+
+    #[clap(
+    long,
+    conflicts_with = "thorough",
+    help = "preset for quick scans: concurrency 100, 500 ms timeout, no retries and the top 100 ports, each overridable by its own flag"
+    )]
+    fast: bool,
+
+    #[clap(
+    long,
+    help = "preset for complete scans: concurrency 16, 3000 ms timeout, 2 retries and all ports, each overridable by its own flag"
+    )]
+    thorough: bool,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "wordlist batches of 64 names buffered ahead of the workers(default is twice the concurrency)"
+    )]
+    channel_capacity: Option<u64>,
+
+    #[clap(
+    long,
+    value_enum,
+    default_value_t = Transport::Udp,
+    help = "protocol used to reach the resolvers(default is udp)"
+    )]
+    transport: Transport,
+
+    #[clap(
+    long,
+    default_value_t = 16,
+    value_parser = clap::value_parser!(u16).range(1..),
+    help = "queries each worker keeps in flight on its TCP connection with --transport tcp(default is 16)"
+    )]
+    pipeline_depth: u16,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "seconds over which workers start, doubling from one up to --concurrency, to avoid early rate limiting(default is all at once)"
+    )]
+    ramp_up: Option<u64>,
+
+    #[clap(
+    long,
+    help = "times a SERVFAIL or timed out query is retried(default is one less than the number of resolvers, 0 with --fast, 2 with --thorough)"
+    )]
+    retries: Option<usize>,
+
+    #[clap(
+    long,
+    value_enum,
+    default_value_t = RetryStrategy::Rotate,
+    help = "same retries on the resolver that failed, keeping each name on one resolver; rotate moves to the next resolver, routing around a bad one and spreading load(default is rotate)"
+    )]
+    retry_strategy: RetryStrategy,
+
+    #[clap(
+    long,
+    default_value = "true",
+    action = clap::ArgAction::Set,
+    help = "retry SERVFAIL answers like timeouts; false accepts them at once, for zones with a broken delegation that fail on every resolver(default is true)"
+    )]
+    retry_servfail: bool,
+
+    #[clap(
+    long,
+    default_value = "0",
+    help = "milliseconds to wait before the first retry, doubling for each further retry up to 10s(default is 0, retrying at once)"
+    )]
+    retry_backoff_ms: u64,
+
+    #[clap(
+    long,
+    default_value = "1",
+    value_parser = parse_jitter,
+    help = "share of each retry backoff that is random, from 0 for a fixed wait to 1 for anywhere between zero and the full wait, so workers don't retry in bursts(default is 1)"
+    )]
+    retry_jitter: f64,
+
+    #[clap(
+    long,
+    value_enum,
+    default_value_t = ResolverStrategy::RoundRobin,
+    help = "round-robin takes the resolvers in turn; random picks one uniformly per query, so workers don't hit the resolvers in lockstep(default is round-robin)"
     )]
-    concurrency: u8,
+    resolver_strategy: ResolverStrategy,
 
     #[clap(
     short,
@@ -68,188 +415,2722 @@ struct Args {
     )]
     subdomains_file: String,
 
+    #[clap(
+    long,
+    conflicts_with = "subdomains_file",
+    help = "skip enumeration and only resolve and scan the target itself, without reading a subdomains file"
+    )]
+    no_wordlist: bool,
+
+    #[clap(
+    long,
+    conflicts_with = "no_wordlist",
+    help = "treat each wordlist entry as a complete hostname instead of a label under --target; --target is then optional and, when given, still resolved and used for wildcard detection"
+    )]
+    fqdn: bool,
+
+    #[clap(
+    long,
+    help = "character separating entries in the subdomains file, e.g. ',' or ' ' (default is newline; newlines always separate entries)"
+    )]
+    delimiter: Option<char>,
+
+    #[clap(
+    long,
+    help = "file of labels or names never to query, one per line with * as a wildcard; applies to the wordlist and to names discovered during the scan"
+    )]
+    deny_file: Option<String>,
+
+    #[clap(
+    long,
+    help = "skip names with fewer labels than this, counting the target's labels"
+    )]
+    min_labels: Option<usize>,
+
+    #[clap(
+    long,
+    help = "skip names with more labels than this, counting the target's labels"
+    )]
+    max_labels: Option<usize>,
+
     #[clap(
     short,
     long,
+    env = "PORT_SCANNER_OUTPUT",
     default_value = "./port-scanner.json",
     help = "output file(default is ./port-scanner.json)"
     )]
     output_file: String,
-}
-
-async fn get_hostname_ips(client: &mut AsyncClient, hostname: &str) -> Option<Vec<IpAddr>> {
-    match Name::from_str(&hostname) {
-        Ok(hostname) => {
-            let query = client.query(hostname, DNSClass::IN, RecordType::A);
-            match query.await {
-                Ok(response) => {
-                    let mut addresses: Vec<IpAddr> = vec![];
 
-                    for response in response.answers() {
-                        match response.data() {
-                            Some(record) => match record {
-                                RData::A(record) => {
-                                    addresses.push(IpAddr::V4(record.to_owned()))
-                                }
-                                _ => {}
-                            },
-                            None => {}
-                        }
-                    }
+    #[clap(
+    long,
+    help = "also write each resolved subdomain to this file, one name per line, as it is found"
+    )]
+    names_out: Option<String>,
 
-                    if addresses.len() > 0 {
-                        Some(addresses)
-                    } else {
-                        None
-                    }
-                } Err(err) => {
-                    match err.kind() {
-                        trust_dns_client::error::ClientErrorKind::Timeout => {
-                            None
-                        } _ => {
-                            info!("Query Error: {:?}", err);
-                            None
-                        }
-                    }
-                }
-            }
-        }
-        Err(err) => {
-            info!("Error creating Hostname: {:?}", err);
-            None
-        }
-    }
-}
+    #[clap(
+    long,
+    help = "write every name to this file as it is queried, one per line: the wordlist expanded under the target plus names discovered from certificates, PTR records and redirects"
+    )]
+    dump_candidates: Option<String>,
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .without_time()
-        .init();
+    #[clap(
+    long,
+    help = "also record results in this SQLite database, adding to what earlier runs stored and tracking when each name was first and last seen"
+    )]
+    sqlite: Option<String>,
 
-    let args = Args::parse();
+    #[clap(
+    long,
+    help = "stamp each subdomain with first_seen and last_seen, carrying first_seen over from the result already in the output file"
+    )]
+    timestamp_findings: bool,
 
-    info!("Target: {:?}", args.target);
-    info!("DNS Resolver: {:?}", args.dns_resolver);
-    info!("Concurrency: {:?}", args.concurrency);
-    info!("Subdomains file: {:?}", args.subdomains_file);
-    info!("Output file: {:?}", args.output_file);
+    #[clap(
+    long,
+    help = "earlier result file; only subdomains and addresses it doesn't have are written"
+    )]
+    baseline: Option<String>,
 
-    let (s, r): (Sender<String>, Receiver<String>) = UnboundedChannel();
-    let target = args.target;
-    let dns_resolver = args.dns_resolver;
-    let output_file = args.output_file;
-    let concurrency = args.concurrency as usize;
-    let subdomains_file = args.subdomains_file;
-    let timeout = Duration::from_secs(1);
-    let stream = UdpClientStream::<UdpSocket>::with_timeout(dns_resolver, timeout);
-    let client = AsyncClient::connect(stream);
-    let (mut client, bg) = client.await.expect("connection failed");
-
-    tokio::spawn(bg);
-
-    let root_ips = get_hostname_ips(&mut client, &target).await.unwrap_or_else(Vec::new);
-    let root_domain = Arc::new(Mutex::new(RootDomain {
-        name: target.clone(),
-        subdomains: vec![],
-        addresses: root_ips.into_iter().map(|ip| Address { ip }).collect(),
-    }));
-    let found_count = Arc::new(Mutex::new(0));
-    let file_subdomains = fs::File::open(subdomains_file).expect("Couldn't read subdomains file");
-    let reader = std::io::BufReader::new(file_subdomains);
-    let subdomains: Vec<String> = reader
-        .lines()
-        .map(|l| l.expect("Couldn't read line"))
-        .collect();
-    let subdomains_len = subdomains.len();
-    let progress_bar = ProgressBar::new(subdomains_len as u64);
-    progress_bar.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-        .expect("Couldn't set progress bar style")
-        .progress_chars("##-"));
-    let progress_send = progress_bar.clone();
-    let mut handles = vec![];
+    #[clap(
+    long,
+    requires = "baseline",
+    help = "with --baseline, write the full diff against it (added/removed subdomains, changed IPs and ports) instead of the result"
+    )]
+    diff: bool,
 
-    for _ in 0..concurrency {
-        let r = r.clone();
-        let progress_send = progress_send.clone();
-        let found_count_scan = Arc::clone(&found_count);
-        let root_domain_scan = Arc::clone(&root_domain);
-        let stream = UdpClientStream::<UdpSocket>::with_timeout(dns_resolver, timeout);
-        let client = AsyncClient::connect(stream);
-        let (mut client, bg) = client.await.expect("connection failed");
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "scan the wordlist this many names at a time, rewriting the output file with everything found so far after each chunk"
+    )]
+    chunk_size: Option<u64>,
 
-        tokio::spawn(bg);
+    #[clap(
+    long,
+    conflicts_with_all = ["debug_rcodes", "sni_vhosts", "global_dedup", "baseline", "sqlite"],
+    help = "keep memory flat on huge wordlists: scans in chunks of 10000 names unless --chunk-size is given, appends each chunk's findings to the JSON output and drops them, and buffers a single batch ahead of the workers unless --channel-capacity is given; JSON output only, and not with --debug-rcodes, --sni-vhosts, --global-dedup, --baseline or --sqlite, which need every name held until the end"
+    )]
+    low_memory: bool,
 
-        let handle = tokio::spawn(async move {
-            while let Ok(subdomain) = r.recv().await {
-                let hostname = format!("{}", subdomain);
+    #[clap(
+    long,
+    help = "stop at the first subdomain that resolves and write only that result"
+    )]
+    first_match: bool,
 
-                match get_hostname_ips(&mut client, &hostname).await {
-                    Some(addresses) => {
-                        if !addresses.is_empty() {
-                            let subdomain_struct = Subdomain {
-                                name: subdomain,
-                                addresses: addresses.iter()
-                                    .map(|ip| Address { ip: *ip })
-                                    .collect::<Vec<Address>>(),
-                            };
+    #[clap(
+    long,
+    default_value = "65536",
+    help = "largest network accepted as --target without --force-large-range(default is 65536 addresses, a /16)"
+    )]
+    max_range_size: u64,
 
-                            info!("Found {} addresses for {}", addresses.len(), hostname);
-                            info!("Addresses: {:?}", addresses);
-                            info!("Found {:?}", hostname);
+    #[clap(
+    long,
+    help = "scan a network target even when it has more addresses than --max-range-size"
+    )]
+    force_large_range: bool,
 
-                            {
-                                let mut found_count = found_count_scan.lock().await;
-                                *found_count += 1;
-                            }
+    #[clap(
+    long,
+    help = "id stored in the output metadata, to tell scans apart once ingested(default is a random UUID)"
+    )]
+    scan_id: Option<String>,
 
-                            {
-                                let mut root_domain = root_domain_scan.lock().await;
-                                root_domain.subdomains.push(subdomain_struct);
-                            }
+    #[clap(
+    long,
+    value_parser = metadata::parse_tag,
+    help = "key=value tag stored in the output metadata, repeatable"
+    )]
+    tag: Vec<(String, String)>,
 
-                            info!("Found {:?}", hostname);
-                        }
-                    } None => {
-                        warn!("No IP addresses found for {}", hostname);
-                    }
-                }
+    #[clap(
+    long,
+    help = "flag subdomains whose CNAME points at a service known to allow takeovers"
+    )]
+    takeover: bool,
 
-                progress_send.inc(1);
-            }
-        });
+    #[clap(
+    long,
+    help = "JSON file of extra takeover fingerprints ([{\"service\": ..., \"cname\": ...}])"
+    )]
+    takeover_rules_file: Option<String>,
 
-        handles.push(handle);
-    }
+    #[clap(
+    long,
+    alias = "no-recursion-desired",
+    help = "clear the recursion desired bit, for querying an authoritative nameserver directly; caching resolvers then only answer from cache"
+    )]
+    no_recurse: bool,
 
-    for subdomain in subdomains {
-        let host = subdomain + "." + &target;
-        s.send(host).await.unwrap();
-    }
-    drop(s);
+    #[clap(
+    long = "dns-0x20",
+    help = "randomize the letter case of each query name and drop answers that don't echo it back (0x20 encoding)"
+    )]
+    dns_0x20: bool,
 
-    join_all(handles).await;
+    #[clap(
+    long,
+    help = "set the DNSSEC OK bit and record whether each answer came back authenticated (AD bit), which relies on the resolver validating"
+    )]
+    dnssec: bool,
 
-    progress_bar.finish_with_message("Done!");
+    #[clap(
+    long,
+    default_value = "A",
+    value_parser = parse_record_type,
+    help = "record type to query for each name: A, AAAA, CNAME, MX, TXT, NS, SRV or PTR (default is A)"
+    )]
+    record_type: RecordType,
 
-    {
-        let found_count = found_count.lock().await;
-        info!("Found {} subdomains.", found_count);
-    }
+    #[clap(
+    long,
+    value_parser = parse_record_type,
+    value_delimiter = ',',
+    help = "extra record types queried for every found name and stored with it, e.g. txt,mx"
+    )]
+    also_collect: Vec<RecordType>,
 
-    let root_domain = Arc::try_unwrap(root_domain)
-        .expect("Handle to mutex got leaked")
-        .into_inner();
-    let json = serde_json::to_string(&root_domain).expect("Couldn't serialize root domain");
+    #[clap(
+    long,
+    help = "query the _acme-challenge TXT record of every found name and store any tokens with it"
+    )]
+    acme: bool,
 
-    info!("JSON: {}", json);
+    #[clap(
+    long,
+    help = "keep querying AAAA even after it keeps failing for names that resolve over A(default is to stop AAAA queries after 20 such failures in a row)"
+    )]
+    keep_aaaa: bool,
 
-    fs::File::create(&output_file).expect("Could not create output file")
-        .write_all(json.as_bytes())
-        .expect("Could not write output");
+    #[clap(
+    long,
+    help = "query AAAA alongside A for every name, concurrently, and scan the IPv6 addresses too (only with the default A record type)"
+    )]
+    dual_stack: bool,
 
-    info!("Wrote output to {}", output_file);
+    #[clap(
+    long,
+    help = "add a hosts_by_ip section listing each unique IP once with the names pointing at it"
+    )]
+    global_dedup: bool,
+
+    #[clap(
+    short,
+    long,
+    help = "TCP ports to scan on every resolved address, e.g. 22,80,8000-8100(default is none, the top 100 with --fast, all with --thorough)"
+    )]
+    ports: Option<String>,
+
+    #[clap(
+    long,
+    help = "print the ports a scan would use, after --ports, --fast or --thorough and the ports other flags add, as a list --ports accepts, then exit"
+    )]
+    list_ports: bool,
+
+    #[clap(
+    long,
+    help = "keep closed (refused) and filtered (timed out or unreachable) ports in the result next to open ones, each with its state"
+    )]
+    port_states: bool,
+
+    #[clap(
+    long,
+    help = "keep the first line each open port sends right after the connect, such as an SSH or SMTP greeting, as its banner; connect scans only(default is off, on with --thorough)"
+    )]
+    banners: bool,
+
+    #[clap(
+    long,
+    help = "print every option as this run would use it, from the command line, environment variables, defaults or the --fast/--thorough profile, as JSON, then exit"
+    )]
+    print_config: bool,
+
+    #[clap(
+    long,
+    value_enum,
+    default_value_t = ScanType::Connect,
+    help = "connect, or syn/fin/null/xmas with raw sockets (raw-socket feature, root or CAP_NET_RAW)(default is connect)"
+    )]
+    scan_type: ScanType,
+
+    #[clap(
+    long,
+    help = "record the response code of every name, including unresolved ones, in the output"
+    )]
+    debug_rcodes: bool,
+
+    #[clap(
+    long,
+    help = "include the DNS latency percentiles and histogram printed at the end of the scan in the output summary"
+    )]
+    latency_stats: bool,
+
+    #[clap(
+    long,
+    help = "read the certificate names of hosts with 443 open and enumerate the ones under the target (adds 443 to --ports)"
+    )]
+    tls_sans: bool,
+
+    #[clap(
+    long,
+    help = "handshake with every address that has 443 open once per found name as SNI, recording which names its certificate covers, which get another certificate and which are refused (adds 443 to --ports)"
+    )]
+    sni_vhosts: bool,
+
+    #[clap(
+    long,
+    help = "follow the HTTP redirects of sites on 80 and 443 and record each hop on the port, enumerating redirect hosts under the target (adds 80 and 443 to --ports)"
+    )]
+    follow_redirects: bool,
+
+    #[clap(
+    long,
+    help = "also ask the target zone's nameserver (found through NS records) for each found name and flag answers that differ from the resolvers'"
+    )]
+    compare_authoritative: bool,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u16).range(2..),
+    help = "also ask this many resolvers at once about each found name and flag names they give different addresses for, a sign of geo/CDN splits or poisoning"
+    )]
+    consensus: Option<u16>,
+
+    #[clap(
+    long,
+    help = "look up PTR names of found addresses and enumerate the ones under the target"
+    )]
+    reverse_lookup: bool,
+
+    #[clap(
+    long,
+    help = "keep only the addresses of found subdomains that match this regex, dropping subdomains left without any, e.g. '^10\\.'"
+    )]
+    ip_regex: Option<regex::Regex>,
+
+    #[clap(
+    long,
+    help = "keep only the addresses with a PTR name matching this regex, dropping subdomains left without any, e.g. 'internal'; implies --reverse-lookup"
+    )]
+    ptr_regex: Option<regex::Regex>,
+
+    #[clap(
+    long,
+    help = "maximum sockets open at once for queries and connects(default is derived from the open file limit)"
+    )]
+    max_open_sockets: Option<usize>,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "maximum DNS queries outstanding at once across all workers and pipelined requests, retries included(default is unlimited)"
+    )]
+    max_inflight: Option<u32>,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "maximum port probes in flight at once, independent of --concurrency(default is only limited by --max-open-sockets)"
+    )]
+    scan_concurrency: Option<u32>,
+
+    #[clap(
+    long,
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "maximum concurrent connects to any single IP, to keep per-host pressure low(default is unlimited)"
+    )]
+    max_per_host: Option<u32>,
+
+    #[clap(
+    long,
+    help = "probe each host's ports in random order instead of ascending"
+    )]
+    randomize_ports: bool,
+
+    #[clap(
+    long,
+    requires = "randomize_ports",
+    help = "seed for --randomize-ports, so reruns probe each host in the same order(default is a fresh random order every run)"
+    )]
+    port_seed: Option<u64>,
+
+    #[clap(
+    long,
+    value_delimiter = ',',
+    help = "drop resolved addresses inside these CIDR ranges from the results without scanning them, repeatable or comma separated"
+    )]
+    exclude_cidr: Vec<Cidr>,
+
+    #[clap(
+    long,
+    value_delimiter = ',',
+    help = "keep only subdomains with at least one address inside these CIDR ranges, applied before --exclude-cidr; repeatable or comma separated"
+    )]
+    in_scope_cidr: Vec<Cidr>,
+
+    #[clap(
+    long,
+    value_delimiter = ',',
+    help = "never probe these addresses or CIDR ranges when the target is a network, e.g. its gateway or broadcast address; repeatable or comma separated"
+    )]
+    exclude_host: Vec<Cidr>,
+
+    #[clap(
+    long,
+    alias = "format",
+    value_enum,
+    value_delimiter = ',',
+    default_value = "json",
+    help = "json writes the output file, table prints an aligned table to stdout, hosts writes /etc/hosts lines to the output file with a .hosts extension, sqlite records the results in the output file with a .db extension like --sqlite; repeat it to write every format to the output file with the format's extension(default is json)"
+    )]
+    output_format: Vec<OutputFormat>,
+
+    #[clap(
+    long,
+    value_enum,
+    help = "compress the output file(default is picked from a .gz or .zst output file name, otherwise uncompressed)"
+    )]
+    compress: Option<Compression>,
+
+    #[clap(
+    long,
+    help = "drop subdomains whose addresses all match a wildcard record of the target, or of their own level for deeper names"
+    )]
+    filter_wildcards: bool,
+
+    #[clap(
+    long,
+    default_value_t = 3,
+    help = "random names resolved to learn the wildcard addresses(default is 3)"
+    )]
+    wildcard_probes: usize,
+
+    #[clap(short, long, help = "log debug details such as why each wildcard match was dropped")]
+    verbose: bool,
+
+    #[clap(
+    long,
+    help = "shell command run for each found subdomain, given the name and IPs as arguments and JSON on stdin"
+    )]
+    on_found: Option<String>,
+
+    #[clap(
+    long,
+    default_value_t = 4,
+    value_parser = clap::value_parser!(u16).range(1..),
+    help = "maximum --on-found commands running at once(default is 4)"
+    )]
+    on_found_concurrency: u16,
+
+    #[clap(
+    long,
+    help = "POST the subdomains found since the last post as JSON to this http or https URL while the scan runs; a failed post is retried with the next one"
+    )]
+    webhook: Option<Url>,
+
+    #[clap(
+    long,
+    default_value_t = 10,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "seconds between --webhook posts(default is 10)"
+    )]
+    webhook_interval: u64,
+
+    #[clap(
+    long,
+    requires = "webhook",
+    env = "PORT_SCANNER_WEBHOOK_AUTH",
+    hide_env_values = true,
+    help = "Authorization header value sent with every --webhook post, such as \"Bearer <token>\""
+    )]
+    webhook_auth: Option<String>,
+
+    #[clap(
+    long,
+    default_value_t = 10000,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "most findings kept for --webhook while it's failing; past that the oldest are dropped(default is 10000)"
+    )]
+    webhook_max_pending: u64,
+
+    #[clap(
+    long,
+    help = "write the scan's progress as JSON (done, total, percent, rate, ETA and counts) to this file every --progress-interval seconds; a named pipe gets one JSON line per update"
+    )]
+    progress_file: Option<String>,
+
+    #[clap(
+    long,
+    default_value_t = 5,
+    value_parser = clap::value_parser!(u64).range(1..),
+    help = "seconds between --progress-file updates(default is 5)"
+    )]
+    progress_interval: u64,
+
+    #[clap(
+    long,
+    help = "show a live dashboard instead of the progress bar and logs, p pauses and q stops the scan early(falls back to the progress bar when stdout isn't a terminal)"
+    )]
+    tui: bool,
+
+    #[clap(
+    long,
+    conflicts_with = "tui",
+    help = "print only the number of resolved subdomains to stdout and write no output file, logging to stderr"
+    )]
+    count_only: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[clap(about = "enumerate subdomains of --target and scan them, the same as giving no subcommand")]
+    Enumerate,
+    #[clap(about = "connect-scan host:port or ip:port targets from a file without enumerating, the same as --targets-file")]
+    Scan {
+        #[clap(help = "file of targets, one per line")]
+        targets_file: String,
+    },
+    #[clap(about = "query the resolvers for one name and print the answer")]
+    Resolve {
+        #[clap(help = "name to look up, with the record type of --record-type")]
+        name: String,
+    },
+    #[clap(about = "look up PTR names of an IP address or network and scan its addresses, without a wordlist")]
+    Reverse {
+        #[clap(help = "IP address or network such as 10.0.0.0/24")]
+        range: Cidr,
+    },
+    #[clap(about = "run an HTTP server that accepts scan requests on POST /scan")]
+    Serve {
+        #[clap(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "address to listen on(default is 127.0.0.1:8080)"
+        )]
+        listen: SocketAddr,
+
+        #[clap(
+        long,
+        default_value_t = 100_000,
+        help = "most wordlist entries one request may send(default is 100000)"
+        )]
+        max_wordlist: usize,
+
+        #[clap(
+        long,
+        default_value_t = 1024,
+        help = "most ports one request may ask to scan(default is 1024)"
+        )]
+        max_ports: usize,
+    },
+    #[clap(about = "compare two result files and report added/removed subdomains, changed IPs and ports")]
+    Diff {
+        #[clap(help = "older result file")]
+        old: String,
+
+        #[clap(help = "newer result file")]
+        new: String,
+
+        #[clap(
+        short,
+        long,
+        default_value = "./port-scanner-diff.json",
+        help = "JSON diff output file(default is ./port-scanner-diff.json)"
+        )]
+        output_file: String,
+    },
+}
+
+impl Args {
+    /// Turns the `enumerate`, `scan` and `reverse` subcommands into the flags they stand for, so
+    /// the rest of `run` handles them like the flat form. A `--target` from `PORT_SCANNER_TARGET`
+    /// gives way to the subcommand's own targets; only one typed on the command line is an error.
+    fn into_flat(mut self, matches: &ArgMatches) -> Result<Self> {
+        let typed_target = matches.value_source("target") == Some(ValueSource::CommandLine);
+
+        match self.command.take() {
+            Some(Command::Enumerate) => match &self.target {
+                Some(target) if target.parse::<IpAddr>().is_ok() || target.contains('/') => {
+                    bail!("enumerate needs a domain, use the reverse subcommand for {}", target)
+                }
+                None if !self.fqdn => bail!("enumerate needs --target, or --fqdn with a wordlist of full hostnames"),
+                _ if self.targets_file.is_some() => bail!("enumerate doesn't take --targets-file, use the scan subcommand"),
+                _ => {}
+            },
+            Some(Command::Scan { targets_file }) => {
+                if typed_target {
+                    bail!("scan takes its targets from the file, not from --target");
+                }
+                self.target = None;
+                self.targets_file = Some(targets_file);
+            }
+            Some(Command::Reverse { range }) => {
+                if typed_target {
+                    bail!("reverse takes its range as an argument, not from --target");
+                }
+                self.target = Some(range.to_string());
+            }
+            command => self.command = command,
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct QueryOptions {
+    recursion_desired: bool,
+    randomize_case: bool,
+    record_type: RecordType,
+    dnssec: bool,
+}
+
+#[derive(Clone)]
+struct ScanConfig {
+    dns_resolvers: Vec<SocketAddr>,
+    concurrency: usize,
+    ramp_up: Option<Duration>,
+    timeout: Duration,
+    channel_capacity: usize,
+    transport: Transport,
+    pipeline_depth: usize,
+    retry: RetryPolicy,
+    resolver_strategy: ResolverStrategy,
+    resolver_rate_limits: Option<Arc<ResolverRateLimits>>,
+    /// Permits for `--max-inflight`, shared by every resolver pool.
+    inflight: Option<Arc<Semaphore>>,
+    query_options: QueryOptions,
+    also_collect: Vec<RecordType>,
+    acme: bool,
+    keep_aaaa: bool,
+    dual_stack: bool,
+    fingerprints: Option<Arc<Fingerprints>>,
+    global_dedup: bool,
+    ports: Vec<u16>,
+    scan_type: ScanType,
+    port_shuffle: Option<PortShuffle>,
+    port_states: bool,
+    banners: bool,
+    label_limits: LabelLimits,
+    deny: DenyList,
+    debug_rcodes: bool,
+    latency_stats: bool,
+    /// Shared by every scan with this config, so chunks and server scans append to one file.
+    names_out: Option<Arc<fs::File>>,
+    /// Like `names_out`, for every name queried rather than every name found.
+    candidates_out: Option<Arc<fs::File>>,
+    sockets: Arc<Semaphore>,
+    scan_slots: Arc<Semaphore>,
+    host_limits: Arc<HostLimits>,
+    wildcards: Option<Arc<WildcardDetector>>,
+    on_found: Option<Arc<FoundHook>>,
+    webhook: Option<Arc<Webhook>>,
+    webhook_interval: Duration,
+    progress_file: Option<Arc<ProgressFile>>,
+    progress_interval: Duration,
+    certificates: Option<Arc<CertificateCollector>>,
+    sni_vhosts: bool,
+    redirects: Option<Arc<RedirectFollower>>,
+    compare_authoritative: bool,
+    consensus: Option<usize>,
+    reverse_lookup: bool,
+    answer_filter: AnswerFilter,
+    exclude_cidrs: Vec<Cidr>,
+    in_scope_cidrs: Vec<Cidr>,
+    exclude_hosts: Vec<Cidr>,
+    pause: Arc<PauseSwitch>,
+    tui: bool,
+    first_match: bool,
+    /// Wordlist entries are complete hostnames, not labels under the target.
+    fqdn: bool,
+    /// Set with `--fqdn` and no `--target`: the target only names the result, so it is neither
+    /// resolved nor probed for wildcards or nameservers.
+    target_is_label: bool,
+}
+
+/// Bounds on the names queued for resolution, on top of the DNS limits of 253 octets per name
+/// and 63 per label that `Name::from_str` would otherwise reject on every query.
+#[derive(Debug, Clone, Copy, Default)]
+struct LabelLimits {
+    min_labels: Option<usize>,
+    max_labels: Option<usize>,
+}
+
+impl LabelLimits {
+    fn allows(&self, hostname: &str) -> bool {
+        let labels = hostname.split('.').count();
+
+        fits_dns_limits(hostname)
+            && self.min_labels.is_none_or(|min| labels >= min)
+            && self.max_labels.is_none_or(|max| labels <= max)
+    }
+}
+
+fn fits_dns_limits(hostname: &str) -> bool {
+    hostname.len() <= MAX_NAME_LEN
+        && hostname.split('.').all(|label| !label.is_empty() && label.len() <= MAX_LABEL_LEN)
+}
+
+/// Warns up front when joining wordlist entries to `target` breaks the DNS length limits for
+/// some of them, since those names are skipped rather than queried.
+fn warn_overlong_names(target: &str, subdomains: &[String]) {
+    let entries: HashSet<&str> = subdomains.iter().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    let overlong = entries
+        .iter()
+        .filter(|subdomain| !fits_dns_limits(&format!("{}.{}", subdomain, target)))
+        .count();
+
+    if overlong == 0 {
+        return;
+    }
+
+    warn!(
+        "{} of {} wordlist entries are too long to query under {} ({} characters): names are limited to {} characters and labels to {}, so entries can be at most {} characters here",
+        overlong,
+        entries.len(),
+        target,
+        target.len(),
+        MAX_NAME_LEN,
+        MAX_LABEL_LEN,
+        MAX_NAME_LEN.saturating_sub(target.len() + 1),
+    );
+}
+
+enum ScanResult {
+    Found(Box<Subdomain>),
+    Unresolved(Unresolved),
+}
+
+#[derive(Default)]
+struct Resolution {
+    addresses: Vec<IpAddr>,
+    /// CNAME chain from the queried name, in order.
+    cnames: Vec<String>,
+    /// Answers of the queried type in presentation format, for types other than A and AAAA.
+    records: Vec<String>,
+    /// Lowest TTL of the answer records, `None` without answers.
+    ttl: Option<u32>,
+    /// `None` when no response arrived (timeout, network error or an unparseable name).
+    rcode: Option<ResponseCode>,
+    /// Round trip of the query, `None` when no response arrived.
+    elapsed: Option<Duration>,
+    /// Resolver that produced this result, set by `ResolverPool`.
+    resolver: Option<SocketAddr>,
+    /// AD bit of the response, only read with `--dnssec`.
+    authenticated: Option<bool>,
+}
+
+impl Resolution {
+    /// RCODE mnemonic such as `NXDOMAIN` or `REFUSED`, or `TIMEOUT` when nothing answered.
+    fn rcode_name(&self) -> String {
+        match self.rcode {
+            Some(rcode) => format!("{:?}", rcode).to_uppercase(),
+            None => "TIMEOUT".to_string(),
+        }
+    }
+
+    /// Folds the AAAA answer for the same name into this A answer. Either side failing keeps
+    /// what the other found: the RCODE, resolver and CNAMEs come from whichever has addresses.
+    fn merge_aaaa(mut self, aaaa: Resolution) -> Resolution {
+        if self.addresses.is_empty() && !aaaa.addresses.is_empty() {
+            self.rcode = aaaa.rcode;
+            self.resolver = aaaa.resolver;
+        } else {
+            self.rcode = self.rcode.or(aaaa.rcode);
+            self.resolver = self.resolver.or(aaaa.resolver);
+        }
+        if self.cnames.is_empty() {
+            self.cnames = aaaa.cnames;
+        }
+        for address in aaaa.addresses {
+            if !self.addresses.contains(&address) {
+                self.addresses.push(address);
+            }
+        }
+        self.ttl = self.ttl.into_iter().chain(aaaa.ttl).min();
+        // Both queries were in flight at once, so the name took as long as the slower one.
+        self.elapsed = self.elapsed.into_iter().chain(aaaa.elapsed).max();
+        self.authenticated = match (self.authenticated, aaaa.authenticated) {
+            (Some(a), Some(aaaa)) => Some(a && aaaa),
+            (a, aaaa) => a.or(aaaa),
+        };
+
+        self
+    }
+}
+
+/// Resolves `hostname` with `options`, plus AAAA at the same time when `dual_stack` is set
+/// for an A lookup and `aaaa` hasn't been given up on. The answers are merged.
+async fn resolve_name(pool: &ResolverPool, hostname: &str, options: QueryOptions, dual_stack: bool, aaaa: &AaaaHealth) -> Resolution {
+    if !dual_stack || options.record_type != RecordType::A || !aaaa.enabled() {
+        return pool.resolve(hostname, options).await;
+    }
+
+    let aaaa_options = QueryOptions { record_type: RecordType::AAAA, ..options };
+    let (a_answer, aaaa_answer) = futures::join!(pool.resolve(hostname, options), pool.resolve(hostname, aaaa_options));
+    if !a_answer.addresses.is_empty() {
+        aaaa.record(aaaa_answer.rcode);
+    }
+
+    a_answer.merge_aaaa(aaaa_answer)
+}
+
+/// Same message `DnsHandle::lookup` builds, plus EDNS with the DNSSEC OK bit so a validating
+/// resolver reports whether the answer was authenticated.
+fn dnssec_request(query: Query, options: DnsRequestOptions) -> DnsRequest {
+    let mut message = Message::new();
+    message
+        .add_query(query)
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(options.recursion_desired);
+    message
+        .extensions_mut()
+        .get_or_insert_with(Edns::new)
+        .set_max_payload(EDNS_PAYLOAD_LEN)
+        .set_version(0)
+        .set_dnssec_ok(true);
+
+    DnsRequest::new(message, options)
+}
+
+/// Flips the case of each ASCII letter at random, so a spoofed answer has to guess the
+/// exact casing of the question on top of the transaction id (0x20 encoding).
+fn randomize_case(hostname: &str) -> String {
+    let mut rng = rand::thread_rng();
+
+    hostname
+        .chars()
+        .map(|c| if rng.gen() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+        .collect()
+}
+
+const QUERYABLE_RECORD_TYPES: &[RecordType] = &[
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::CNAME,
+    RecordType::MX,
+    RecordType::TXT,
+    RecordType::NS,
+    RecordType::SRV,
+    RecordType::PTR,
+];
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    rate.parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| format!("invalid rate {}, expected a positive number", rate))
+}
+
+fn parse_jitter(jitter: &str) -> Result<f64, String> {
+    jitter.parse::<f64>()
+        .ok()
+        .filter(|jitter| (0.0..=1.0).contains(jitter))
+        .ok_or_else(|| format!("invalid jitter {}, expected a number from 0 to 1", jitter))
+}
+
+fn parse_record_type(record_type: &str) -> Result<RecordType, String> {
+    RecordType::from_str(&record_type.to_uppercase())
+        .ok()
+        .filter(|record_type| QUERYABLE_RECORD_TYPES.contains(record_type))
+        .ok_or_else(|| format!("unsupported record type {}, expected one of {:?}", record_type, QUERYABLE_RECORD_TYPES))
+}
+
+/// Queries `hostname` for `record_type`. A and AAAA answers are collected as addresses and
+/// CNAMEs as cnames; answers of any other queried type are kept verbatim in `records`.
+async fn query_record_type(client: &mut AsyncClient, hostname: &str, record_type: RecordType, options: QueryOptions) -> Resolution {
+    let hostname = if options.randomize_case {
+        randomize_case(hostname)
+    } else {
+        hostname.to_string()
+    };
+
+    match Name::from_str(&hostname) {
+        Ok(hostname) => {
+            let mut query = Query::query(hostname.clone(), record_type);
+            query.set_query_class(DNSClass::IN);
+
+            let mut request_options = DnsRequestOptions::default();
+            request_options.use_edns = client.is_using_edns();
+            request_options.recursion_desired = options.recursion_desired;
+
+            let started = Instant::now();
+            let query = if options.dnssec {
+                client.send(dnssec_request(query, request_options))
+            } else {
+                client.lookup(query, request_options)
+            }.first_answer();
+            match query.await {
+                Ok(response) => {
+                    if options.randomize_case
+                        && !response.queries().iter().any(|query| query.name().eq_case(&hostname))
+                    {
+                        warn!("Dropping answer for {} that didn't echo the query case", hostname);
+                        return Resolution::default();
+                    }
+
+                    let mut addresses: Vec<IpAddr> = vec![];
+                    let mut cname_records: Vec<(String, String)> = vec![];
+                    let mut records: Vec<String> = vec![];
+                    let mut ttl: Option<u32> = None;
+
+                    for response in response.answers() {
+                        ttl = Some(ttl.map_or(response.ttl(), |ttl| ttl.min(response.ttl())));
+                        match response.data() {
+                            Some(RData::A(record)) => {
+                                addresses.push(IpAddr::V4(record.to_owned()))
+                            }
+                            Some(RData::AAAA(record)) => {
+                                addresses.push(IpAddr::V6(record.to_owned()))
+                            }
+                            Some(RData::CNAME(record)) => {
+                                cname_records.push((response.name().to_string(), record.to_string()))
+                            }
+                            _ => {}
+                        }
+
+                        if response.record_type() == record_type
+                            && !matches!(record_type, RecordType::A | RecordType::AAAA)
+                        {
+                            if let Some(data) = response.data() {
+                                records.push(data.to_string());
+                            }
+                        }
+                    }
+
+                    Resolution {
+                        addresses,
+                        cnames: cname_chain(&hostname.to_string(), &cname_records),
+                        records,
+                        ttl,
+                        rcode: Some(response.response_code()),
+                        elapsed: Some(started.elapsed()),
+                        resolver: None,
+                        authenticated: options.dnssec.then(|| response.authentic_data()),
+                    }
+                } Err(err) => {
+                    match err.kind() {
+                        trust_dns_client::proto::error::ProtoErrorKind::Timeout => {
+                            Resolution::default()
+                        } _ => {
+                            info!("Query Error: {:?}", err);
+                            Resolution::default()
+                        }
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            info!("Error creating Hostname: {:?}", err);
+            Resolution::default()
+        }
+    }
+}
+
+/// Follows the `(owner, target)` CNAME records of an answer from `hostname`, which resolvers
+/// don't always return in order. Stops after `MAX_CNAME_HOPS` or when a target repeats;
+/// records that aren't on the chain are dropped.
+fn cname_chain(hostname: &str, cname_records: &[(String, String)]) -> Vec<String> {
+    let normalize = |name: &str| name.trim_end_matches('.').to_lowercase();
+    let mut chain: Vec<String> = vec![];
+    let mut current = normalize(hostname);
+
+    while chain.len() < MAX_CNAME_HOPS {
+        let Some((_, target)) = cname_records.iter().find(|(owner, _)| normalize(owner) == current) else {
+            break;
+        };
+        let next = normalize(target);
+        if next == normalize(hostname) || chain.iter().any(|hop| normalize(hop) == next) {
+            warn!("CNAME loop at {} while following {}", target, hostname);
+            break;
+        }
+
+        chain.push(target.clone());
+        current = next;
+    }
+
+    if chain.len() == MAX_CNAME_HOPS {
+        debug!("Stopped following the CNAME chain of {} after {} hops", hostname, MAX_CNAME_HOPS);
+    }
+
+    chain
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn resolve_addresses(ips: Vec<IpAddr>, ports: &[u16], connect_timeout: Duration, sockets: &Semaphore, scan_slots: &Semaphore, host_limits: &HostLimits, scan_type: ScanType, shuffle: Option<PortShuffle>, all_states: bool, banners: bool) -> Vec<Address> {
+    let mut addresses = vec![];
+
+    for ip in ips {
+        let ports = if ports.is_empty() {
+            vec![]
+        } else {
+            ports::scan_ports(ip, ports, connect_timeout, sockets, scan_slots, host_limits, scan_type, shuffle, all_states, banners).await
+        };
+
+        addresses.push(Address { ip, ports, ptr: vec![] });
+    }
+
+    addresses
+}
+
+/// Whether any of `ips` is inside `in_scope`, or true when no scope is set.
+fn in_scope(ips: &[IpAddr], in_scope: &[Cidr]) -> bool {
+    in_scope.is_empty() || ips.iter().any(|ip| in_scope.iter().any(|cidr| cidr.contains(*ip)))
+}
+
+/// Removes the addresses inside any of `excluded` and returns how many were removed.
+fn exclude_addresses(ips: &mut Vec<IpAddr>, excluded: &[Cidr]) -> usize {
+    let len = ips.len();
+    ips.retain(|ip| !excluded.iter().any(|cidr| cidr.contains(*ip)));
+
+    len - ips.len()
+}
+
+/// Queries `hostname` for each of `record_types` at once and returns the non-empty answers by type.
+/// AAAA is skipped once `aaaa` has given up on it, and otherwise reports to it when the name
+/// already resolved over A.
+async fn collect_records(pool: &ResolverPool, hostname: &str, record_types: &[RecordType], options: QueryOptions, sockets: &Semaphore, aaaa: &AaaaHealth) -> BTreeMap<String, Vec<String>> {
+    let record_types = record_types.iter().filter(|&&record_type| record_type != RecordType::AAAA || aaaa.enabled());
+    let lookups = record_types.map(|&record_type| async move {
+        let _permit = sockets.acquire().await.expect("socket semaphore closed");
+        let resolution = pool.resolve(hostname, QueryOptions { record_type, ..options }).await;
+        if record_type == RecordType::AAAA && options.record_type == RecordType::A {
+            aaaa.record(resolution.rcode);
+        }
+        let answers = match record_type {
+            RecordType::A | RecordType::AAAA => resolution.addresses.iter().map(IpAddr::to_string).collect(),
+            _ => resolution.records,
+        };
+
+        (record_type.to_string(), answers)
+    });
+
+    join_all(lookups)
+        .await
+        .into_iter()
+        .filter(|(_, answers)| !answers.is_empty())
+        .collect()
+}
+
+/// TXT records of `_acme-challenge.<hostname>`, where ACME DNS-01 validation puts its tokens.
+async fn acme_challenge(pool: &ResolverPool, hostname: &str, options: QueryOptions) -> Vec<String> {
+    let options = QueryOptions { record_type: RecordType::TXT, ..options };
+
+    pool.resolve(&format!("_acme-challenge.{}", hostname), options).await.records
+}
+
+/// PTR names of `ip`, without the trailing dot.
+async fn ptr_names(pool: &ResolverPool, ip: IpAddr, options: QueryOptions) -> Vec<String> {
+    let options = QueryOptions { record_type: RecordType::PTR, ..options };
+
+    pool.resolve(&Name::from(ip).to_string(), options).await
+        .records
+        .into_iter()
+        .map(|name| name.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+/// Queues names discovered mid-scan that haven't been queued before, skipping names that break
+/// the DNS length limits like wordlist entries are. `queued` is the visited set that stops
+/// certificate and PTR names from feeding each other in a loop. Names `deny` lists are dropped.
+#[allow(clippy::too_many_arguments)]
+fn queue_discovered(
+    names: Vec<String>,
+    source: &str,
+    queued: &std::sync::Mutex<HashSet<String>>,
+    deny: &DenyList,
+    target: &str,
+    pending: &AtomicUsize,
+    s: &Sender<Vec<String>>,
+    progress: &ProgressBar,
+) {
+    let found = names.len();
+    let names: Vec<String> = names.into_iter().filter(|name| !deny.denies(name, target)).collect();
+    if names.len() < found {
+        info!("Denied {} names from {} listed in the deny file", found - names.len(), source);
+    }
+    let found = names.len();
+    let names: Vec<String> = names.into_iter().filter(|name| fits_dns_limits(name)).collect();
+    if names.len() < found {
+        warn!("Skipped {} names from {} that are too long to query", found - names.len(), source);
+    }
+
+    let names: Vec<String> = names
+        .into_iter()
+        .filter(|name| queued.lock().expect("queued names lock poisoned").insert(name.clone()))
+        .collect();
+
+    if names.is_empty() {
+        return;
+    }
+
+    info!("Queueing {:?} from {}", names, source);
+    pending.fetch_add(names.len(), Ordering::AcqRel);
+    progress.inc_length(names.len() as u64);
+
+    // Sent from a separate task so a full channel can't stall every worker.
+    let s = s.clone();
+    tokio::spawn(async move { s.send(names).await });
+}
+
+/// Fails for a network with more than `max_size` addresses unless `force` is set, so a typo such
+/// as `/8` for `/24` doesn't start a scan that never ends.
+fn check_range_size(range: Cidr, max_size: u64, force: bool) -> Result<()> {
+    if range.size() <= u128::from(max_size) {
+        return Ok(());
+    }
+    if !force {
+        anyhow::bail!(
+            "{} has {} addresses, more than --max-range-size {}; pass --force-large-range to scan it anyway",
+            range, range.size(), max_size,
+        );
+    }
+
+    warn!("Scanning all {} addresses of {}, as --force-large-range asks", range.size(), range);
+    Ok(())
+}
+
+/// Inspects the addresses of `range`, given as the target: their PTR names and `config.ports`.
+/// There are no subdomains to enumerate, so the result only has the root entry. Addresses are
+/// generated lazily, skipping `config.exclude_hosts`, and `config.concurrency` of them inspected
+/// at once. Beyond a single address, only those with a PTR name or an open port are kept.
+async fn scan_range(config: &ScanConfig, range: Cidr) -> Result<RootDomain> {
+    let pool = ResolverPool::connect(&config.dns_resolvers, config.transport, config.timeout, config.retry, 0).await?
+        .with_strategy(config.resolver_strategy)
+        .with_rate_limits(config.resolver_rate_limits.clone())
+        .with_inflight_limit(config.inflight.clone());
+    let pool = &pool;
+    let single = range.size() == 1;
+    let excluded = AtomicUsize::new(0);
+    let hosts = range.hosts().filter(|&ip| {
+        let skip = config.exclude_hosts.iter().any(|host| host.contains(ip));
+        if skip {
+            excluded.fetch_add(1, Ordering::Relaxed);
+        }
+        !skip
+    });
+
+    let mut addresses: Vec<Address> = futures::stream::iter(hosts)
+        .map(|ip| async move {
+            let mut addresses = resolve_addresses(vec![ip], &config.ports, config.timeout, &config.sockets, &config.scan_slots, &config.host_limits, config.scan_type, config.port_shuffle, config.port_states, config.banners).await;
+
+            for address in &mut addresses {
+                address.ptr = ptr_names(pool, address.ip, config.query_options).await;
+                if single || !address.ptr.is_empty() {
+                    info!("PTR names of {}: {:?}", address.ip, address.ptr);
+                }
+            }
+
+            addresses
+        })
+        .buffer_unordered(config.concurrency)
+        .flat_map(futures::stream::iter)
+        .filter(|address| futures::future::ready(single || address.ports.iter().any(Port::is_open) || !address.ptr.is_empty()))
+        .collect()
+        .await;
+    if !config.answer_filter.is_empty() {
+        config.answer_filter.retain(&mut addresses);
+    }
+    addresses.sort_unstable_by_key(|address| address.ip);
+    if !config.exclude_hosts.is_empty() {
+        info!("Skipped {} addresses inside {:?}", excluded.load(Ordering::Relaxed), config.exclude_hosts);
+    }
+    if !single {
+        info!("{} of the {} addresses in {} have a PTR name or an open port", addresses.len(), range.size(), range);
+    }
+
+    let mut root_domain = RootDomain {
+        name: range.to_string(),
+        subdomain_count: 0,
+        total_addresses: 0,
+        unique_ip_count: 0,
+        subdomains: vec![],
+        addresses,
+        hosts_by_ip: None,
+        unresolved: vec![],
+        wildcard_signatures: vec![],
+        latency: None,
+        vhosts: vec![],
+        metadata: None,
+    };
+    root_domain.summarize();
+
+    Ok(root_domain)
+}
+
+/// Connect-scans `targets` from `--targets-file` as given, with no enumeration and regardless
+/// of `--ports`. IP targets skip DNS and land in the root entry; hostnames are resolved once
+/// and become subdomains. Every address is kept, so one without ports had all its ports closed.
+async fn scan_targets(config: &ScanConfig, path: &str, targets: Vec<Target>) -> Result<RootDomain> {
+    let pool = ResolverPool::connect(&config.dns_resolvers, config.transport, config.timeout, config.retry, 0).await?
+        .with_strategy(config.resolver_strategy)
+        .with_rate_limits(config.resolver_rate_limits.clone())
+        .with_inflight_limit(config.inflight.clone());
+    let pool = &pool;
+    let aaaa = AaaaHealth::new(config.keep_aaaa);
+    let aaaa = &aaaa;
+
+    let mut ports_by_host: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+    for target in targets {
+        ports_by_host.entry(target.host).or_default().push(target.port);
+    }
+
+    let results: Vec<(String, Option<Resolution>, Vec<Address>)> = futures::stream::iter(ports_by_host)
+        .map(|(host, ports)| async move {
+            let (ips, resolution) = match host.parse::<IpAddr>() {
+                Ok(ip) => (vec![ip], None),
+                Err(_) => {
+                    let resolution = resolve_name(pool, &host, config.query_options, config.dual_stack, aaaa).await;
+                    (resolution.addresses.clone(), Some(resolution))
+                }
+            };
+
+            // Every state is needed for the log, and only kept in the result with --port-states.
+            let mut addresses = resolve_addresses(ips, &ports, config.timeout, &config.sockets, &config.scan_slots, &config.host_limits, config.scan_type, config.port_shuffle, true, config.banners).await;
+            for address in &mut addresses {
+                for &port in &ports {
+                    let state = address.ports.iter().find(|probed| probed.port == port).map_or(PortState::Filtered, |probed| probed.state);
+                    info!("{} ({}) port {} is {}", host, address.ip, port, state);
+                }
+                if !config.port_states {
+                    address.ports.retain(Port::may_be_open);
+                }
+            }
+
+            (host, resolution, addresses)
+        })
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+
+    let mut root_domain = RootDomain {
+        name: path.to_string(),
+        subdomain_count: 0,
+        total_addresses: 0,
+        unique_ip_count: 0,
+        subdomains: vec![],
+        addresses: vec![],
+        hosts_by_ip: None,
+        unresolved: vec![],
+        wildcard_signatures: vec![],
+        latency: None,
+        vhosts: vec![],
+        metadata: None,
+    };
+    for (host, resolution, addresses) in results {
+        let Some(resolution) = resolution else {
+            root_domain.addresses.extend(addresses);
+            continue;
+        };
+        let query_ms = resolution.elapsed.map(|elapsed| elapsed.as_millis() as u64);
+        if addresses.is_empty() {
+            warn!("No IP addresses found for {} ({})", host, resolution.rcode_name());
+            root_domain.unresolved.push(Unresolved { name: host, rcode: resolution.rcode_name(), query_ms });
+            continue;
+        }
+
+        root_domain.subdomains.push(Subdomain {
+            name: host,
+            addresses,
+            cnames: resolution.cnames,
+            takeover: None,
+            rcode: None,
+            query_ms: query_ms.unwrap_or_default(),
+            resolver: resolution.resolver,
+            records: vec![],
+            ttl: resolution.ttl,
+            authoritative_mismatch: None,
+            resolver_answers: None,
+            sans: vec![],
+            dnssec_ok: resolution.authenticated,
+            extra_records: BTreeMap::new(),
+            acme_challenge: vec![],
+            first_seen: None,
+            last_seen: None,
+        });
+    }
+    root_domain.addresses.sort_unstable_by_key(|address| address.ip);
+    root_domain.subdomains.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    root_domain.unresolved.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    root_domain.summarize();
+
+    Ok(root_domain)
+}
+
+/// Prints what the resolvers answer for `name`, one CNAME, address or record per line.
+async fn resolve_one(config: &ScanConfig, name: &str) -> Result<()> {
+    let pool = ResolverPool::connect(&config.dns_resolvers, config.transport, config.timeout, config.retry, 0).await?
+        .with_strategy(config.resolver_strategy)
+        .with_rate_limits(config.resolver_rate_limits.clone())
+        .with_inflight_limit(config.inflight.clone());
+    let hostname = name.trim_end_matches('.');
+    let resolution = pool.resolve(hostname, config.query_options).await;
+    if resolution.rcode.is_none() {
+        bail!("No response for {} from {:?}", hostname, config.dns_resolvers);
+    }
+    info!("{} answered {} for {}", resolution.resolver.map_or_else(|| "?".to_string(), |resolver| resolver.to_string()), resolution.rcode_name(), hostname);
+
+    for cname in &resolution.cnames {
+        println!("CNAME\t{}", cname.trim_end_matches('.'));
+    }
+    for address in &resolution.addresses {
+        println!("{}\t{}", if address.is_ipv4() { "A" } else { "AAAA" }, address);
+    }
+    for record in &resolution.records {
+        println!("{}\t{}", config.query_options.record_type, record);
+    }
+
+    Ok(())
+}
+
+/// What a scan of `target` sets up before querying any of its wordlist: the resolver
+/// connections, the target's own entry, its wildcard and authoritative answers, and the names
+/// queued so far. `scan_chunks` prepares it once and runs every chunk against it.
+struct TargetScan {
+    /// One pool per worker, each starting the rotation at a different resolver.
+    worker_pools: Vec<Arc<ResolverPool>>,
+    aaaa: Arc<AaaaHealth>,
+    addresses: Vec<Address>,
+    excluded: usize,
+    wildcards: Arc<WildcardSet>,
+    authoritative: Option<Arc<Authoritative>>,
+    /// Names already queued, so a name found on several certificates, PTR records or redirects,
+    /// or in an earlier chunk, is only enumerated once.
+    queued: Option<Arc<std::sync::Mutex<HashSet<String>>>>,
+}
+
+impl TargetScan {
+    async fn prepare(config: &ScanConfig, target: &str) -> Result<Self> {
+        let query_options = config.query_options;
+        let timeout = config.timeout;
+        let pool = ResolverPool::connect(&config.dns_resolvers, config.transport, timeout, config.retry, 0).await?
+            .with_strategy(config.resolver_strategy)
+            .with_rate_limits(config.resolver_rate_limits.clone())
+            .with_inflight_limit(config.inflight.clone());
+        // Workers connect only to the resolvers that did here, so a dead one is reported once.
+        let resolvers = pool.resolvers();
+        let mut worker_pools = vec![];
+        for worker in 0..config.concurrency {
+            let pool = ResolverPool::reconnect(&resolvers, config.transport, timeout, config.retry, worker).await?
+                .with_strategy(config.resolver_strategy)
+                .with_rate_limits(config.resolver_rate_limits.clone())
+                .with_inflight_limit(config.inflight.clone());
+            worker_pools.push(Arc::new(pool));
+        }
+
+        let aaaa = Arc::new(AaaaHealth::new(config.keep_aaaa));
+        let mut root_ips = if config.target_is_label {
+            vec![]
+        } else {
+            let _permit = config.sockets.acquire().await?;
+            resolve_name(&pool, target, query_options, config.dual_stack, &aaaa).await.addresses
+        };
+        let excluded = exclude_addresses(&mut root_ips, &config.exclude_cidrs);
+        let addresses = resolve_addresses(root_ips, &config.ports, timeout, &config.sockets, &config.scan_slots, &config.host_limits, config.scan_type, config.port_shuffle, config.port_states, config.banners).await;
+        let wildcards = match &config.wildcards {
+            Some(detector) if !config.target_is_label => {
+                detector.detect(&pool, target, query_options, &config.sockets).await
+            }
+            _ => Arc::new(WildcardSet::default()),
+        };
+        let authoritative = if config.compare_authoritative && !config.target_is_label {
+            let _permit = config.sockets.acquire().await?;
+            Authoritative::discover(&pool, target, query_options, config.transport, timeout, config.retry).await.map(Arc::new)
+        } else {
+            None
+        };
+        let queued = (config.certificates.is_some() || config.reverse_lookup || config.redirects.is_some())
+            .then(|| Arc::new(std::sync::Mutex::new(HashSet::new())));
+
+        Ok(TargetScan { worker_pools, aaaa, addresses, excluded, wildcards, authoritative, queued })
+    }
+}
+
+/// Resolves `target` and every `subdomains` label under it, scanning `config.ports` on each
+/// resolved address.
+async fn scan(config: &ScanConfig, target: &str, subdomains: Vec<String>, show_progress: bool) -> Result<RootDomain> {
+    let subdomains = dedup_wordlist(subdomains);
+    let prepared = TargetScan::prepare(config, target).await?;
+    let progress = ScanProgress::start(config, target, &subdomains, None, show_progress);
+    let mut root_domain = scan_names(config, target, &prepared, &progress, subdomains).await?;
+    progress.finish().await?;
+    finish_scan(config, &mut root_domain).await;
+
+    Ok(root_domain)
+}
+
+/// Adds what needs every found name at once, the SNI virtual hosts and the names grouped by
+/// address, and fills in the summary counts.
+async fn finish_scan(config: &ScanConfig, root_domain: &mut RootDomain) {
+    if config.sni_vhosts {
+        root_domain.vhosts = probe_vhosts(root_domain, !config.target_is_label, config.timeout, &config.sockets).await;
+    }
+
+    if config.global_dedup {
+        root_domain.hosts_by_ip = Some(root_domain.hosts_by_ip());
+    }
+    root_domain.summarize();
+}
+
+/// Progress of one target's scan, shared by all of its chunks, so the bar and the
+/// `--progress-file` snapshots count the whole wordlist and the last snapshot is written once.
+/// The TUI lives here too, so one dashboard covers every chunk and quitting stops them all.
+struct ScanProgress {
+    bar: ProgressBar,
+    /// Set when the user quits from the TUI, or by the first match with `--first-match`.
+    /// Workers skip the names still queued, so the scan winds down and still returns what it
+    /// found so far, and no further chunk starts.
+    cancelled: Arc<AtomicBool>,
+    first_match: bool,
+    dashboard: Option<Arc<Dashboard>>,
+    dashboard_ui: Option<tokio::task::JoinHandle<()>>,
+    counts: Arc<ScanCounts>,
+    progress_file: Option<Arc<ProgressFile>>,
+    pause_indicator: tokio::task::JoinHandle<()>,
+    ticker: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// The counters workers bump and the `--progress-file` ticker reads.
+struct ScanCounts {
+    target: String,
+    found: AtomicUsize,
+    wildcards: AtomicUsize,
+    /// The chunk being scanned, counted from 1, out of `chunks`.
+    chunk: AtomicUsize,
+    chunks: Option<usize>,
+}
+
+impl ScanCounts {
+    fn snapshot(&self, bar: &ProgressBar, finished: bool) -> Progress<'_> {
+        let chunk = self.chunks.map(|chunks| (self.chunk.load(Ordering::Relaxed), chunks));
+        Progress::of(bar, &self.target, self.found.load(Ordering::Relaxed), self.wildcards.load(Ordering::Relaxed), chunk, finished)
+    }
+}
+
+impl ScanProgress {
+    /// `subdomains` is the whole deduplicated wordlist, split into `chunks` chunks if any.
+    fn start(config: &ScanConfig, target: &str, subdomains: &[String], chunks: Option<usize>, show_progress: bool) -> Self {
+        let total = subdomains.iter().filter(|subdomain| !subdomain.trim().is_empty()).count() as u64;
+        let tui = show_progress && config.tui;
+        let bar = if show_progress && !tui {
+            ProgressBar::new(total)
+        } else {
+            // Still counts, the dashboard and the progress file read its position and length.
+            ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::hidden())
+        };
+        bar.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
+            .expect("Couldn't set progress bar style")
+            .progress_chars("##-"));
+        let mut paused = config.pause.subscribe();
+        let pause_indicator = {
+            let bar = bar.clone();
+            tokio::spawn(async move {
+                while paused.changed().await.is_ok() {
+                    bar.set_message(if *paused.borrow() { "paused" } else { "" });
+                }
+            })
+        };
+        let counts = Arc::new(ScanCounts {
+            target: target.to_string(),
+            found: AtomicUsize::new(0),
+            wildcards: AtomicUsize::new(0),
+            chunk: AtomicUsize::new(1),
+            chunks,
+        });
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let dashboard = tui.then(|| Arc::new(Dashboard::new(target, bar.clone())));
+        let dashboard_ui = dashboard.as_ref().map(|dashboard| {
+            let cancelled = Arc::clone(&cancelled);
+            let pause = Arc::clone(&config.pause);
+
+            tui::spawn(Arc::clone(dashboard), Arc::clone(&config.pause), move || {
+                cancelled.store(true, Ordering::Release);
+                // Paused workers would never see the queue drain.
+                if *pause.subscribe().borrow() {
+                    pause.toggle();
+                }
+            })
+        });
+        let ticker = config.progress_file.clone().map(|progress_file| {
+            let interval = config.progress_interval;
+            let (bar, counts) = (bar.clone(), Arc::clone(&counts));
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    progress_file.write(&counts.snapshot(&bar, false));
+                }
+            })
+        });
+
+        ScanProgress {
+            bar,
+            cancelled,
+            first_match: config.first_match,
+            dashboard,
+            dashboard_ui,
+            counts,
+            progress_file: config.progress_file.clone(),
+            pause_indicator,
+            ticker,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// `index` counts from 0, like `chunks().enumerate()`.
+    fn start_chunk(&self, index: usize) {
+        self.counts.chunk.store(index + 1, Ordering::Relaxed);
+    }
+
+    /// Takes `names` the scan won't query after all off the total.
+    fn skip(&self, names: usize) {
+        self.bar.set_length(self.bar.length().unwrap_or(0).saturating_sub(names as u64));
+    }
+
+    async fn finish(self) -> Result<()> {
+        self.pause_indicator.abort();
+        if let Some(ticker) = self.ticker {
+            ticker.abort();
+        }
+        // Read before the bar finishes, which fills it up to its length.
+        if let Some(progress_file) = &self.progress_file {
+            progress_file.write(&self.counts.snapshot(&self.bar, true));
+        }
+        let skipped = self.bar.length().unwrap_or(0).saturating_sub(self.bar.position());
+        self.bar.finish_with_message("Done!");
+        if let (Some(dashboard), Some(dashboard_ui)) = (&self.dashboard, self.dashboard_ui) {
+            dashboard.finish();
+            dashboard_ui.await?;
+        }
+        if self.cancelled.load(Ordering::Acquire) && !self.first_match {
+            warn!("Scan stopped early, {} queued names were skipped", skipped);
+        }
+
+        Ok(())
+    }
+}
+
+/// Queries the `subdomains` labels under `target` with the setup in `prepared`, returning the
+/// target's entry with the names found.
+async fn scan_names(config: &ScanConfig, target: &str, prepared: &TargetScan, progress: &ScanProgress, subdomains: Vec<String>) -> Result<RootDomain> {
+    // Bounded so the producer waits for workers instead of buffering the whole wordlist.
+    let (s, r): (Sender<Vec<String>>, Receiver<Vec<String>>) = BoundedChannel(config.channel_capacity);
+    let query_options = config.query_options;
+    let timeout = config.timeout;
+    let aaaa = &prepared.aaaa;
+    let excluded_count = Arc::new(AtomicUsize::new(prepared.excluded));
+    let mut root_domain = RootDomain {
+        name: target.to_string(),
+        subdomain_count: 0,
+        total_addresses: 0,
+        unique_ip_count: 0,
+        subdomains: vec![],
+        addresses: prepared.addresses.clone(),
+        hosts_by_ip: None,
+        unresolved: vec![],
+        wildcard_signatures: prepared.wildcards.signatures().to_vec(),
+        latency: None,
+        vhosts: vec![],
+        metadata: None,
+    };
+    let wildcards = &prepared.wildcards;
+    let authoritative = &prepared.authoritative;
+    let mismatch_count = Arc::new(AtomicUsize::new(0));
+    let disagreement_count = Arc::new(AtomicUsize::new(0));
+    let counts = &progress.counts;
+    let (found_before, wildcards_before) = (counts.found.load(Ordering::Relaxed), counts.wildcards.load(Ordering::Relaxed));
+    let out_of_scope_count = Arc::new(AtomicUsize::new(0));
+    let filtered_count = Arc::new(AtomicUsize::new(0));
+    // Blank lines and repeated entries never reach a worker, so drop them here to keep the
+    // progress bar total equal to the number of queries that will actually run.
+    let mut seen = HashSet::new();
+    let subdomains: Vec<String> = subdomains
+        .into_iter()
+        .map(|l| l.trim().to_lowercase())
+        .filter(|l| !l.is_empty() && seen.insert(l.clone()))
+        .collect();
+    let candidates_len = subdomains.len();
+    let hostnames: Vec<String> = subdomains
+        .iter()
+        .map(|subdomain| {
+            if config.fqdn {
+                subdomain.trim_end_matches('.').to_string()
+            } else {
+                format!("{}.{}", subdomain, target)
+            }
+        })
+        .filter(|hostname| config.label_limits.allows(hostname))
+        .collect();
+    let subdomains_len = hostnames.len();
+    if subdomains_len < candidates_len {
+        warn!("Skipped {} invalid or out of range names", candidates_len - subdomains_len);
+    }
+    let hostnames: Vec<String> = hostnames.into_iter().filter(|hostname| !config.deny.denies(hostname, target)).collect();
+    if hostnames.len() < subdomains_len {
+        info!("Denied {} names listed in the deny file", subdomains_len - hostnames.len());
+    }
+    let hostnames: Vec<String> = match &prepared.queued {
+        Some(queued) => {
+            let mut queued = queued.lock().expect("queued names lock poisoned");
+            hostnames.into_iter().filter(|hostname| queued.insert(hostname.clone())).collect()
+        }
+        None => hostnames,
+    };
+    let subdomains_len = hostnames.len();
+    progress.skip(candidates_len - subdomains_len);
+    let queued = prepared.queued.clone();
+    // Names queued but not yet resolved, plus one held by the producer until the wordlist is
+    // queued. Workers can queue certificate names too, so the channel is closed once this
+    // drops to zero rather than when the producer finishes.
+    let pending = Arc::new(AtomicUsize::new(1));
+    let progress_bar = &progress.bar;
+    let progress_send = progress_bar.clone();
+    let (cancelled, dashboard) = (&progress.cancelled, &progress.dashboard);
+    let (results_s, results_r): (Sender<ScanResult>, Receiver<ScanResult>) = UnboundedChannel();
+    // The collector is the only owner of the results, so workers never wait on each other to record a finding.
+    let debug_rcodes = config.debug_rcodes;
+    let on_found = config.on_found.clone();
+    let webhook = config.webhook.clone();
+    let dashboard_collect = dashboard.clone();
+    let first_match = config.first_match;
+    let names_out = config.names_out.clone();
+    let collector = tokio::spawn(async move {
+        let mut latencies = LatencyHistogram::new();
+
+        while let Ok(result) = results_r.recv().await {
+            match result {
+                ScanResult::Found(subdomain) => {
+                    latencies.record(subdomain.query_ms);
+                    // Workers already resolving when the first match stopped the scan can still find names.
+                    if first_match && !root_domain.subdomains.is_empty() {
+                        continue;
+                    }
+                    if let Some(file) = &names_out {
+                        // Unbuffered so the file can be tailed while the scan runs.
+                        if let Err(err) = writeln!(file.as_ref(), "{}", subdomain.name) {
+                            warn!("Couldn't write {} to the names file: {}", subdomain.name, err);
+                        }
+                    }
+                    if let Some(dashboard) = &dashboard_collect {
+                        dashboard.record_found(&subdomain.name, subdomain.addresses.len());
+                    }
+                    if let Some(on_found) = &on_found {
+                        on_found.start(subdomain.as_ref().clone()).await;
+                    }
+                    if let Some(webhook) = &webhook {
+                        webhook.record(subdomain.as_ref().clone());
+                    }
+                    root_domain.subdomains.push(*subdomain);
+                }
+                ScanResult::Unresolved(unresolved) => {
+                    if let Some(query_ms) = unresolved.query_ms {
+                        latencies.record(query_ms);
+                    }
+                    if debug_rcodes {
+                        root_domain.unresolved.push(unresolved);
+                    }
+                }
+            }
+        }
+
+        if let Some(on_found) = &on_found {
+            on_found.wait().await;
+        }
+
+        (root_domain, latencies.stats())
+    });
+    // Posts on a timer rather than per finding, so a slow webhook never holds up the collector.
+    let webhook_ticker = config.webhook.clone().map(|webhook| {
+        let target = target.to_string();
+        let interval = config.webhook_interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                webhook.flush(&target).await;
+            }
+        })
+    });
+    let mut handles = vec![];
+    let ramp_up = config.ramp_up.map(|window| Arc::new(RampUp::start(config.concurrency, window)));
+
+    for worker in 0..config.concurrency {
+        let r = r.clone();
+        let progress_send = progress_send.clone();
+        let counts = Arc::clone(counts);
+        let wildcards = Arc::clone(wildcards);
+        let detector = config.wildcards.clone();
+        let results_s = results_s.clone();
+        let fingerprints = config.fingerprints.clone();
+        let ports = config.ports.clone();
+        let sockets = Arc::clone(&config.sockets);
+        let scan_slots = Arc::clone(&config.scan_slots);
+        let host_limits = Arc::clone(&config.host_limits);
+        let certificates = config.certificates.clone();
+        let redirects = config.redirects.clone();
+        let authoritative = authoritative.clone();
+        let mismatch_count = Arc::clone(&mismatch_count);
+        let consensus = config.consensus;
+        let disagreement_count = Arc::clone(&disagreement_count);
+        let queued = queued.clone();
+        let pending = Arc::clone(&pending);
+        let s = s.clone();
+        let target = target.to_string();
+        let pool = Arc::clone(&prepared.worker_pools[worker]);
+        let pipeline_depth = config.pipeline_depth;
+        let scan_type = config.scan_type;
+        let port_shuffle = config.port_shuffle;
+        let port_states = config.port_states;
+        let banners = config.banners;
+        let first_match = config.first_match;
+        let dual_stack = config.dual_stack;
+        let reverse_lookup = config.reverse_lookup;
+        let exclude_cidrs = config.exclude_cidrs.clone();
+        let in_scope_cidrs = config.in_scope_cidrs.clone();
+        let out_of_scope_count = Arc::clone(&out_of_scope_count);
+        let answer_filter = config.answer_filter.clone();
+        let deny = config.deny.clone();
+        let filtered_count = Arc::clone(&filtered_count);
+        let excluded_count = Arc::clone(&excluded_count);
+        let ramp_up = ramp_up.clone();
+        let also_collect = config.also_collect.clone();
+        let acme = config.acme;
+        let aaaa = Arc::clone(aaaa);
+        let pause = Arc::clone(&config.pause);
+        let cancelled = Arc::clone(cancelled);
+        let dashboard = dashboard.clone();
+        let candidates_out = config.candidates_out.clone();
+
+        let handle = tokio::spawn(async move {
+            let (pool, sockets, scan_slots, wildcards, fingerprints, ports, certificates, queued) =
+                (&pool, &sockets, &scan_slots, &wildcards, &fingerprints, &ports, &certificates, &queued);
+            let (pending, s, target, results_s, progress_send, pause) =
+                (&pending, &s, &target, &results_s, &progress_send, &pause);
+            let counts = &counts;
+            let (cancelled, dashboard, candidates_out) = (&cancelled, &dashboard, &candidates_out);
+            let (exclude_cidrs, excluded_count, also_collect, host_limits) =
+                (&exclude_cidrs, &excluded_count, &also_collect, &host_limits);
+            let (in_scope_cidrs, out_of_scope_count, aaaa, detector) = (&in_scope_cidrs, &out_of_scope_count, &aaaa, &detector);
+            let (redirects, authoritative, mismatch_count) = (&redirects, &authoritative, &mismatch_count);
+            let disagreement_count = &disagreement_count;
+            let (answer_filter, filtered_count, deny) = (&answer_filter, &filtered_count, &deny);
+
+            if let Some(ramp_up) = &ramp_up {
+                ramp_up.wait().await;
+            }
+
+            while let Ok(batch) = r.recv().await {
+                futures::stream::iter(batch).for_each_concurrent(pipeline_depth, |subdomain| async move {
+                    let hostname = subdomain.clone();
+
+                    pause.wait().await;
+
+                    if cancelled.load(Ordering::Acquire) {
+                        if pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                            s.close();
+                        }
+                        return;
+                    }
+
+                    if let Some(file) = candidates_out {
+                        // One write per line, so lines from concurrent workers don't interleave.
+                        if let Err(err) = file.as_ref().write_all(format!("{}\n", hostname).as_bytes()) {
+                            warn!("Couldn't write {} to the candidates file: {}", hostname, err);
+                        }
+                    }
+
+                    let resolution = {
+                        let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                        resolve_name(pool, &hostname, query_options, dual_stack, aaaa).await
+                    };
+                    if let Some(dashboard) = dashboard {
+                        let failed = matches!(resolution.rcode, None | Some(ResponseCode::ServFail));
+                        dashboard.record_query(resolution.resolver, failed);
+                    }
+                    let rcode = resolution.rcode_name();
+                    let query_ms = resolution.elapsed.map(|elapsed| elapsed.as_millis() as u64);
+                    let resolver = resolution.resolver;
+
+                    let mut wildcard = wildcards.matches(&resolution.addresses).map(<[IpAddr]>::to_vec);
+                    if let (None, Some(detector)) = (&wildcard, detector) {
+                        if !resolution.addresses.is_empty() {
+                            if let Some(level) = detector.detect_level(pool, &hostname, target, query_options, sockets).await {
+                                wildcard = level.matches(&resolution.addresses).map(<[IpAddr]>::to_vec);
+                            }
+                        }
+                    }
+                    // A CNAME without addresses still means the name exists, and its target is
+                    // what takeover fingerprints match.
+                    let resolved = !resolution.addresses.is_empty() || !resolution.records.is_empty() || !resolution.cnames.is_empty();
+
+                    match resolution {
+                        Resolution { addresses, .. } if wildcard.is_some() => {
+                            info!("Skipping wildcard match {}", hostname);
+                            debug!("{} answered {:?}, identical to wildcard signature {:?}", hostname, addresses, wildcard);
+                            counts.wildcards.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Resolution { addresses, .. } if !addresses.is_empty() && !in_scope(&addresses, in_scope_cidrs) => {
+                            debug!("Dropping {}, none of {:?} is in scope", hostname, addresses);
+                            out_of_scope_count.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Resolution { mut addresses, cnames, records, ttl, authenticated, .. } if resolved => {
+                            let takeover = fingerprints.as_ref()
+                                .and_then(|fingerprints| fingerprints.check(&cnames))
+                                .map(str::to_string);
+
+                            if let Some(service) = &takeover {
+                                error!("{} Potential takeover: {} -> {:?} ({})", logging::finding("TAKEOVER"), hostname, cnames, service);
+                            }
+
+                            if addresses.is_empty() && records.is_empty() {
+                                info!("{} exists only as CNAME {:?}, whose target has no addresses", hostname, cnames);
+                            } else {
+                                info!("Found {} addresses for {}", addresses.len(), hostname);
+                            }
+                            let internal: Vec<&IpAddr> = addresses.iter().filter(|&&ip| cidr::is_internal(ip)).collect();
+                            if !internal.is_empty() {
+                                warn!("{} {} resolves to internal addresses {:?}", logging::finding("PRIVATE IP"), hostname, internal);
+                            }
+                            info!("Addresses: {:?}", addresses);
+                            if !records.is_empty() {
+                                info!("Records: {:?}", records);
+                            }
+                            info!("Found {:?}", hostname);
+
+                            let extra_records = collect_records(pool, &hostname, also_collect, query_options, sockets, aaaa).await;
+                            let acme_challenge = if acme {
+                                let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                                acme_challenge(pool, &hostname, query_options).await
+                            } else {
+                                vec![]
+                            };
+                            if !acme_challenge.is_empty() {
+                                warn!("{} {} has _acme-challenge tokens {:?}", logging::finding("ACME"), hostname, acme_challenge);
+                            }
+                            let authoritative_mismatch = match authoritative {
+                                Some(authoritative) => {
+                                    let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                                    authoritative.compare(&hostname, &addresses, &cnames, query_options).await
+                                }
+                                None => None,
+                            };
+                            if let Some(mismatch) = &authoritative_mismatch {
+                                warn!(
+                                    "Authoritative answer for {} differs: resolvers {:?} {:?}, {} {} {:?} {:?}",
+                                    hostname, addresses, cnames, mismatch.server, mismatch.rcode, mismatch.addresses, mismatch.cnames,
+                                );
+                                mismatch_count.fetch_add(1, Ordering::Relaxed);
+                            }
+                            let resolver_answers = match consensus {
+                                Some(count) => {
+                                    let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                                    consensus::check(pool, &hostname, query_options, count).await
+                                }
+                                None => None,
+                            };
+                            if let Some(answers) = &resolver_answers {
+                                let answers: Vec<String> = answers.iter()
+                                    .map(|answer| format!("{} {} {:?}", answer.resolver, answer.rcode, answer.addresses))
+                                    .collect();
+                                warn!("Resolvers disagree on {}: {}", hostname, answers.join(", "));
+                                disagreement_count.fetch_add(1, Ordering::Relaxed);
+                            }
+
+                            excluded_count.fetch_add(exclude_addresses(&mut addresses, exclude_cidrs), Ordering::Relaxed);
+                            let mut addresses = resolve_addresses(addresses, ports, timeout, sockets, scan_slots, host_limits, scan_type, port_shuffle, port_states, banners).await;
+                            if reverse_lookup {
+                                for address in &mut addresses {
+                                    let _permit = sockets.acquire().await.expect("socket semaphore closed");
+                                    address.ptr = ptr_names(pool, address.ip, query_options).await;
+                                }
+                            }
+                            if let Some(redirects) = redirects {
+                                for address in &mut addresses {
+                                    for port in &mut address.ports {
+                                        if port.is_open() && (port.port == http::HTTP_PORT || port.port == tls::HTTPS_PORT) {
+                                            port.http_redirects = redirects.chain(pool, query_options, &hostname, address.ip, port.port, timeout, sockets).await;
+                                        }
+                                    }
+                                }
+                            }
+                            let https = addresses.iter()
+                                .find(|address| address.ports.iter().any(|port| port.is_open() && port.port == tls::HTTPS_PORT));
+                            let sans = match (&certificates, https) {
+                                (Some(certificates), Some(address)) => {
+                                    certificates.names(address.ip, &hostname, timeout, sockets).await
+                                }
+                                _ => vec![],
+                            };
+
+                            if let Some(queued) = &queued {
+                                let certificate_names = tls::candidates(&sans, target);
+                                let source = format!("the certificate of {}", hostname);
+                                queue_discovered(certificate_names, &source, queued, deny, target, pending, s, progress_send);
+
+                                let suffix = format!(".{}", target);
+                                let ptr_candidates = addresses.iter()
+                                    .flat_map(|address| &address.ptr)
+                                    .filter(|name| name.ends_with(&suffix))
+                                    .cloned()
+                                    .collect();
+                                let source = format!("the PTR records of {}", hostname);
+                                queue_discovered(ptr_candidates, &source, queued, deny, target, pending, s, progress_send);
+
+                                let redirect_candidates = addresses.iter()
+                                    .flat_map(|address| &address.ports)
+                                    .flat_map(|port| &port.http_redirects)
+                                    .filter_map(|redirect| Url::parse(redirect).ok()?.host_str().map(str::to_lowercase))
+                                    .filter(|name| name.ends_with(&suffix))
+                                    .collect();
+                                let source = format!("the HTTP redirects of {}", hostname);
+                                queue_discovered(redirect_candidates, &source, queued, deny, target, pending, s, progress_send);
+                            }
+
+                            let mut subdomain_struct = Subdomain {
+                                name: subdomain,
+                                addresses,
+                                cnames,
+                                takeover,
+                                rcode: debug_rcodes.then_some(rcode),
+                                query_ms: query_ms.unwrap_or_default(),
+                                resolver,
+                                records,
+                                ttl,
+                                authoritative_mismatch,
+                                resolver_answers,
+                                sans,
+                                dnssec_ok: authenticated,
+                                extra_records,
+                                acme_challenge,
+                                first_seen: None,
+                                last_seen: None,
+                            };
+
+                            if !answer_filter.is_empty() && !answer_filter.retain(&mut subdomain_struct.addresses) {
+                                debug!("Dropping {}, none of its addresses matches --ip-regex and --ptr-regex", hostname);
+                                filtered_count.fetch_add(1, Ordering::Relaxed);
+                            } else {
+                                counts.found.fetch_add(1, Ordering::Relaxed);
+                                results_s.send(ScanResult::Found(Box::new(subdomain_struct))).await
+                                    .expect("Result collector exited early");
+
+                                if first_match && !cancelled.swap(true, Ordering::AcqRel) {
+                                    info!("Stopping at the first match, {}", hostname);
+                                    s.close();
+                                }
+
+                                info!("Found {:?}", hostname);
+                            }
+                        } _ => {
+                            warn!("No IP addresses found for {} ({})", hostname, rcode);
+
+                            results_s.send(ScanResult::Unresolved(Unresolved { name: hostname, rcode, query_ms })).await
+                                .expect("Result collector exited early");
+                        }
+                    }
+
+                    progress_send.inc(1);
+
+                    if pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        s.close();
+                    }
+                }).await;
+            }
+
+            if let Some(ramp_up) = ramp_up {
+                ramp_up.close();
+            }
+        });
+
+        handles.push(handle);
+    }
+
+    for batch in hostnames.chunks(QUEUE_BATCH_SIZE) {
+        if cancelled.load(Ordering::Acquire) {
+            break;
+        }
+        pending.fetch_add(batch.len(), Ordering::AcqRel);
+
+        if s.send(batch.to_vec()).await.is_err() {
+            if !cancelled.load(Ordering::Acquire) {
+                warn!("Workers exited before the wordlist was fully queued");
+            }
+            break;
+        }
+    }
+    if pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+        s.close();
+    }
+    drop(s);
+
+    join_all(handles).await;
+    drop(results_s);
+
+    info!("Found {} subdomains.", counts.found.load(Ordering::Relaxed) - found_before);
+
+    if config.wildcards.is_some() {
+        info!("Filtered {} wildcard matches.", counts.wildcards.load(Ordering::Relaxed) - wildcards_before);
+    }
+
+    if authoritative.is_some() {
+        info!("{} subdomains answered differently by the authoritative server.", mismatch_count.load(Ordering::Relaxed));
+    }
+
+    if let Some(count) = config.consensus {
+        info!("{} subdomains got different answers from {} resolvers.", disagreement_count.load(Ordering::Relaxed), count);
+    }
+
+    if !config.in_scope_cidrs.is_empty() {
+        info!("Dropped {} subdomains outside {:?}.", out_of_scope_count.load(Ordering::Relaxed), config.in_scope_cidrs);
+    }
+
+    if !config.answer_filter.is_empty() {
+        info!("Dropped {} subdomains without an address matching the IP and PTR patterns.", filtered_count.load(Ordering::Relaxed));
+    }
+
+    if !config.exclude_cidrs.is_empty() {
+        info!("Excluded {} addresses inside {:?}.", excluded_count.load(Ordering::Relaxed), config.exclude_cidrs);
+    }
+
+    let (mut root_domain, latency) = collector.await?;
+    if let Some(ticker) = webhook_ticker {
+        ticker.abort();
+    }
+    if let Some(webhook) = &config.webhook {
+        webhook.flush(target).await;
+    }
+
+    if let Some(latency) = &latency {
+        info!(
+            "Query latency over {} answers: min {} ms, p50 {} ms, p90 {} ms, p99 {} ms, max {} ms",
+            latency.samples, latency.min, latency.p50, latency.p90, latency.p99, latency.max,
+        );
+        for line in latency.histogram_lines() {
+            info!("{}", line);
+        }
+    }
+    if config.latency_stats {
+        root_domain.latency = latency;
+    }
+    root_domain.summarize();
+
+    Ok(root_domain)
+}
+
+/// Sends every found name, and the target itself when `with_root`, as SNI to every address
+/// with 443 open. Logs the names an address accepts without resolving to it, the virtual hosts
+/// DNS alone wouldn't show.
+async fn probe_vhosts(root_domain: &RootDomain, with_root: bool, timeout: Duration, sockets: &Semaphore) -> Vec<VirtualHosts> {
+    let names = with_root.then_some((&root_domain.name, &root_domain.addresses))
+        .into_iter()
+        .chain(root_domain.subdomains.iter().map(|subdomain| (&subdomain.name, &subdomain.addresses)));
+    let mut hostnames = vec![];
+    let mut resolved: HashSet<(&str, IpAddr)> = HashSet::new();
+    let mut https_ips = BTreeSet::new();
+    for (name, addresses) in names {
+        hostnames.push(name.clone());
+        for address in addresses {
+            resolved.insert((name, address.ip));
+            if address.ports.iter().any(|port| port.is_open() && port.port == tls::HTTPS_PORT) {
+                https_ips.insert(address.ip);
+            }
+        }
+    }
+    if https_ips.is_empty() {
+        return vec![];
+    }
+    info!("Probing {} addresses with 443 open for {} names as SNI", https_ips.len(), hostnames.len());
+
+    let collector = CertificateCollector::new();
+    let vhosts = join_all(https_ips.into_iter().map(|ip| collector.virtual_hosts(ip, &hostnames, timeout, sockets))).await;
+    for virtual_hosts in &vhosts {
+        let hidden: Vec<&String> = virtual_hosts.accepted.iter()
+            .filter(|name| !resolved.contains(&(name.as_str(), virtual_hosts.ip)))
+            .collect();
+        if !hidden.is_empty() {
+            info!("{} also serves {:?} by SNI", virtual_hosts.ip, hidden);
+        }
+    }
+
+    vhosts
+}
+
+fn main() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if args.print_config {
+        println!("{}", serde_json::to_string_pretty(&effective_config(&matches, &args))?);
+        return Ok(());
+    }
+    let args = args.into_flat(&matches)?;
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Some(worker_threads) = args.worker_threads {
+        runtime.worker_threads(worker_threads as usize);
+    }
+
+    runtime.build().context("Couldn't start the async runtime")?.block_on(run(args))
+}
+
+/// Every option as `run` will see it, whether given on the command line, taken from its
+/// environment variable or defaulted, with the profile's presets filled in for options left
+/// unset. Options without a value are left out.
+fn effective_config(matches: &ArgMatches, args: &Args) -> serde_json::Value {
+    let command = Args::command();
+    let mut config = arg_values(&command, matches);
+
+    if let Some(profile) = Profile::from_flags(args.fast, args.thorough) {
+        let defaults = profile.defaults();
+        let presets = [
+            ("concurrency", serde_json::json!(defaults.concurrency.to_string())),
+            ("timeout_ms", serde_json::json!(defaults.timeout.as_millis().to_string())),
+            ("ports", serde_json::json!(ports::format_ports(&defaults.ports))),
+            ("retries", serde_json::json!(defaults.retries.map(|retries| retries.to_string()))),
+        ];
+        for (id, value) in presets {
+            if !value.is_null() {
+                config.entry(id).or_insert(value);
+            }
+        }
+        // A flag is always present, so only a profile that turns it on changes it.
+        if defaults.banners {
+            config.insert("banners".to_string(), serde_json::json!(true));
+        }
+    }
+    if let Some((name, subcommand_matches)) = matches.subcommand() {
+        let subcommand = command.find_subcommand(name).expect("matched subcommand is defined");
+        config.insert("command".to_string(), serde_json::json!({ "name": name, "args": arg_values(subcommand, subcommand_matches) }));
+    }
+
+    serde_json::Value::Object(config)
+}
+
+/// Raw values of the options `matches` has, keyed by option id: booleans for flags, arrays for
+/// repeatable options and strings for the rest. Values hidden from `--help` stay hidden.
+fn arg_values(command: &clap::Command, matches: &ArgMatches) -> serde_json::Map<String, serde_json::Value> {
+    let mut values = serde_json::Map::new();
+
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if matches!(id, "help" | "version" | "print_config") {
+            continue;
+        }
+        let Some(raw) = matches.get_raw(id) else {
+            continue;
+        };
+        let raw: Vec<String> = raw.map(|value| value.to_string_lossy().into_owned()).collect();
+
+        let value = if arg.is_hide_env_values_set() {
+            serde_json::json!("<redacted>")
+        } else {
+            match (arg.get_action(), raw.as_slice()) {
+                (ArgAction::SetTrue | ArgAction::SetFalse, [flag]) => serde_json::json!(flag == "true"),
+                (ArgAction::Append, raw) => serde_json::json!(raw),
+                (_, [value]) => serde_json::json!(value),
+                (_, raw) => serde_json::json!(raw),
+            }
+        };
+        values.insert(id.to_string(), value);
+    }
+
+    values
+}
+
+async fn run(args: Args) -> Result<()> {
+    let tui = args.tui && args.command.is_none() && std::io::stdout().is_terminal();
+
+    // The dashboard owns the terminal, so log lines would only tear it.
+    let logs = tracing_subscriber::fmt()
+        .without_time()
+        .with_max_level(if args.verbose { Level::DEBUG } else { Level::INFO });
+    if tui {
+        logs.with_writer(std::io::sink).init();
+    } else if args.count_only || args.list_ports || matches!(args.command, Some(Command::Resolve { .. })) {
+        // Keeps stdout down to the count, port list or answer, so it can be piped or diffed as is.
+        logs.with_ansi(logging::use_color(&std::io::stderr())).with_writer(std::io::stderr).init();
+    } else {
+        logs.with_ansi(logging::use_color(&std::io::stdout())).init();
+    }
+
+    if args.tui && !tui && args.command.is_none() {
+        warn!("stdout isn't a terminal, showing the progress bar instead of the TUI");
+    }
+
+    // Only compares two files, so it needs none of the resolvers, sockets or listeners below.
+    if let Some(Command::Diff { old, new, output_file }) = &args.command {
+        let diff = diff::diff(&diff::load(old)?, &diff::load(new)?);
+        diff.print_summary();
+
+        fs::write(output_file, serde_json::to_string(&diff)?)?;
+        info!("Wrote diff to {}", output_file);
+
+        return Ok(());
+    }
+
+    info!("Target: {:?}", args.target);
+    let mut dns_resolvers = resolvers::collect(args.dns_resolver, args.resolvers_file.as_deref())?;
+    info!("DNS Resolvers: {:?}", dns_resolvers);
+    info!("Interface: {:?}", args.interface);
+    let profile = Profile::from_flags(args.fast, args.thorough);
+    let defaults = profile.map_or_else(ProfileDefaults::default, Profile::defaults);
+    info!("Profile: {:?}", profile);
+    let concurrency = args.concurrency.unwrap_or(defaults.concurrency);
+    info!("Concurrency: {:?}", concurrency);
+    info!("Worker threads: {:?}", args.worker_threads);
+    let timeout = args.timeout_ms.map_or(defaults.timeout, Duration::from_millis);
+    info!("Timeout: {:?}", timeout);
+    info!("Ramp-up: {:?}", args.ramp_up.map(Duration::from_secs));
+    info!("Transport: {:?}", args.transport);
+    info!("Subdomains file: {:?}", args.subdomains_file);
+    info!("Delimiter: {:?}", args.delimiter.unwrap_or('\n'));
+    info!("Output file: {:?}", args.output_file);
+    info!("Names file: {:?}", args.names_out);
+    info!("Candidates file: {:?}", args.dump_candidates);
+    info!("SQLite database: {:?}", args.sqlite);
+    info!("Timestamp findings: {:?}", args.timestamp_findings);
+    info!("Baseline: {:?} (full diff: {:?})", args.baseline, args.diff);
+    let chunk_size = args.chunk_size.or(args.low_memory.then_some(LOW_MEMORY_CHUNK_SIZE));
+    info!("Low memory: {:?}", args.low_memory);
+    info!("Chunk size: {:?}", chunk_size);
+    info!("First match only: {:?}", args.first_match);
+    info!("Output format: {:?}", args.output_format);
+    let compression = args.compress.or_else(|| Compression::from_path(&args.output_file));
+    info!("Compression: {:?}", compression);
+    info!("Takeover detection: {:?}", args.takeover);
+    info!("Recursion desired: {:?}", !args.no_recurse);
+    info!("0x20 encoding: {:?}", args.dns_0x20);
+    info!("DNSSEC: {:?}", args.dnssec);
+    info!("Record type: {}", args.record_type);
+    info!("Also collecting: {:?}", args.also_collect);
+    info!("ACME challenges: {:?}", args.acme);
+    info!("Dual stack: {:?}", args.dual_stack);
+    info!("Ports: {:?}", args.ports);
+    info!("Scan type: {:?}", args.scan_type);
+    info!("Randomized port order: {:?} (seed {:?})", args.randomize_ports, args.port_seed);
+    info!("TLS certificate names: {:?}", args.tls_sans);
+    info!("SNI virtual hosts: {:?}", args.sni_vhosts);
+    info!("Follow redirects: {:?}", args.follow_redirects);
+    info!("Compare with authoritative: {:?}", args.compare_authoritative);
+    info!("Consensus: {:?}", args.consensus);
+    info!("Reverse lookups: {:?}", args.reverse_lookup || args.ptr_regex.is_some());
+    info!("IP pattern: {:?}", args.ip_regex.as_ref().map(regex::Regex::as_str));
+    info!("PTR pattern: {:?}", args.ptr_regex.as_ref().map(regex::Regex::as_str));
+    info!("In-scope ranges: {:?}", args.in_scope_cidr);
+    info!("Excluded ranges: {:?}", args.exclude_cidr);
+    info!("Excluded hosts: {:?}", args.exclude_host);
+    info!("Webhook: {:?} (every {}s)", args.webhook.as_ref().map(Url::as_str), args.webhook_interval);
+
+    if let Some(name) = &args.interface {
+        interface::bind_to(name)?;
+    }
+
+    let query_options = QueryOptions {
+        recursion_desired: !args.no_recurse,
+        randomize_case: args.dns_0x20,
+        record_type: args.record_type,
+        dnssec: args.dnssec,
+    };
+    if args.validate_resolvers {
+        let control_options = QueryOptions { record_type: RecordType::A, ..query_options };
+        dns_resolvers = resolvers::validate(dns_resolvers, args.transport, timeout, control_options, &args.validation_name).await?;
+    }
+    let consensus = match args.consensus.map(usize::from) {
+        Some(_) if dns_resolvers.len() < 2 => {
+            warn!("--consensus needs at least two resolvers, not comparing answers");
+            None
+        }
+        Some(count) if count > dns_resolvers.len() => {
+            warn!("--consensus {} is more than the {} resolvers, asking all of them", count, dns_resolvers.len());
+            Some(dns_resolvers.len())
+        }
+        count => count,
+    };
+
+    info!("Per-resolver rate: {:?} queries/s", args.resolver_rate);
+    let retries = args.retries.or(defaults.retries).unwrap_or(dns_resolvers.len().saturating_sub(1));
+    info!("Retries: {:?} ({:?})", retries, args.retry_strategy);
+    info!("Retry SERVFAIL: {:?}", args.retry_servfail);
+    info!("Retry backoff: {:?} (jitter {:?})", Duration::from_millis(args.retry_backoff_ms), args.retry_jitter);
+    info!("Resolver strategy: {:?}", args.resolver_strategy);
+
+    let max_open_sockets = args.max_open_sockets
+        .unwrap_or_else(ports::default_max_open_sockets)
+        .clamp(1, Semaphore::MAX_PERMITS);
+    info!("Max open sockets: {:?}", max_open_sockets);
+    info!("Max in-flight queries: {:?}", args.max_inflight);
+
+    info!("Scan concurrency: {:?}", args.scan_concurrency);
+    info!("Max connects per host: {:?}", args.max_per_host);
+    let scan_concurrency = args.scan_concurrency
+        .map_or(Semaphore::MAX_PERMITS, |limit| (limit as usize).min(Semaphore::MAX_PERMITS));
+
+    let channel_capacity = match (args.channel_capacity, args.low_memory) {
+        (Some(capacity), _) => capacity as usize,
+        (None, true) => 1,
+        (None, false) => concurrency as usize * 2,
+    };
+    info!("Channel capacity: {:?} batches", channel_capacity);
+
+    let fingerprints = if args.takeover {
+        Some(Arc::new(Fingerprints::load(args.takeover_rules_file.as_deref())?))
+    } else {
+        None
+    };
+    ports::check_scan_type(args.scan_type)?;
+    let mut ports = match &args.ports {
+        Some(ports) => ports::parse_ports(ports)?,
+        None => defaults.ports,
+    };
+    if let (Some(profile), None) = (profile, &args.ports) {
+        info!("Ports: {} from the {:?} profile", ports.len(), profile);
+    }
+    if (args.tls_sans || args.sni_vhosts) && !ports.contains(&tls::HTTPS_PORT) {
+        ports.push(tls::HTTPS_PORT);
+        ports.sort_unstable();
+    }
+    if args.follow_redirects {
+        for port in [http::HTTP_PORT, tls::HTTPS_PORT] {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports.sort_unstable();
+    }
+    if args.list_ports {
+        println!("{}", ports::format_ports(&ports));
+        return Ok(());
+    }
+    let config = ScanConfig {
+        resolver_rate_limits: args.resolver_rate.map(|rate| Arc::new(ResolverRateLimits::new(&dns_resolvers, rate))),
+        inflight: args.max_inflight.map(|max| Arc::new(Semaphore::new((max as usize).min(Semaphore::MAX_PERMITS)))),
+        dns_resolvers,
+        concurrency: concurrency as usize,
+        ramp_up: args.ramp_up.map(Duration::from_secs),
+        timeout,
+        channel_capacity,
+        transport: args.transport,
+        retry: RetryPolicy {
+            strategy: args.retry_strategy,
+            retries,
+            servfail: args.retry_servfail,
+            backoff: Duration::from_millis(args.retry_backoff_ms),
+            jitter: args.retry_jitter,
+        },
+        resolver_strategy: args.resolver_strategy,
+        // UDP answers aren't pipelined, so each worker keeps to one query at a time there.
+        pipeline_depth: match args.transport {
+            Transport::Udp => 1,
+            Transport::Tcp => args.pipeline_depth as usize,
+        },
+        query_options,
+        also_collect: args.also_collect,
+        acme: args.acme,
+        keep_aaaa: args.keep_aaaa,
+        dual_stack: args.dual_stack,
+        fingerprints,
+        global_dedup: args.global_dedup,
+        ports,
+        scan_type: args.scan_type,
+        port_shuffle: args.randomize_ports.then_some(PortShuffle { seed: args.port_seed }),
+        port_states: args.port_states,
+        banners: args.banners || defaults.banners,
+        label_limits: LabelLimits {
+            min_labels: args.min_labels,
+            max_labels: args.max_labels,
+        },
+        deny: args.deny_file.as_deref().map(DenyList::load).transpose()?.unwrap_or_default(),
+        debug_rcodes: args.debug_rcodes,
+        latency_stats: args.latency_stats,
+        names_out: match &args.names_out {
+            Some(path) => Some(Arc::new(fs::File::create(path).with_context(|| format!("Couldn't create names file {}", path))?)),
+            None => None,
+        },
+        candidates_out: match &args.dump_candidates {
+            Some(path) => Some(Arc::new(fs::File::create(path).with_context(|| format!("Couldn't create candidates file {}", path))?)),
+            None => None,
+        },
+        sockets: Arc::new(Semaphore::new(max_open_sockets)),
+        scan_slots: Arc::new(Semaphore::new(scan_concurrency)),
+        host_limits: Arc::new(HostLimits::new(args.max_per_host.map(|max| max as usize))),
+        wildcards: args.filter_wildcards.then(|| Arc::new(WildcardDetector::new(args.wildcard_probes))),
+        on_found: args.on_found.map(|command| Arc::new(FoundHook::new(command, args.on_found_concurrency))),
+        webhook: args.webhook.map(|url| Webhook::new(url, args.webhook_auth, args.webhook_max_pending as usize).map(Arc::new)).transpose()?,
+        webhook_interval: Duration::from_secs(args.webhook_interval),
+        progress_file: args.progress_file.as_deref().map(ProgressFile::open).transpose()?.map(Arc::new),
+        progress_interval: Duration::from_secs(args.progress_interval),
+        certificates: args.tls_sans.then(|| Arc::new(CertificateCollector::new())),
+        sni_vhosts: args.sni_vhosts,
+        redirects: args.follow_redirects.then(|| Arc::new(RedirectFollower::new())),
+        compare_authoritative: args.compare_authoritative,
+        consensus,
+        // PTR names are what --ptr-regex matches, so it needs them looked up.
+        reverse_lookup: args.reverse_lookup || args.ptr_regex.is_some(),
+        answer_filter: AnswerFilter::new(args.ip_regex, args.ptr_regex),
+        exclude_cidrs: args.exclude_cidr,
+        exclude_hosts: args.exclude_host,
+        in_scope_cidrs: args.in_scope_cidr,
+        pause: Arc::new(PauseSwitch::new()),
+        tui,
+        first_match: args.first_match,
+        fqdn: args.fqdn,
+        target_is_label: args.fqdn && args.target.is_none(),
+    };
+    pause::listen(Arc::clone(&config.pause))?;
+
+    match args.command {
+        Some(Command::Serve { listen, max_wordlist, max_ports }) => {
+            return server::serve(listen, config, server::Limits { max_wordlist, max_ports }).await
+        }
+        Some(Command::Resolve { name }) => return resolve_one(&config, &name).await,
+        Some(Command::Diff { .. }) => unreachable!("handled before any network setup"),
+        Some(Command::Enumerate | Command::Scan { .. } | Command::Reverse { .. }) => unreachable!("folded into flags by into_flat"),
+        None => {}
+    }
+
+    // Results of a targets file, or of a list of full hostnames without a target, are named after the file.
+    let target = args.target
+        .or_else(|| args.targets_file.clone())
+        .or_else(|| args.fqdn.then(|| args.subdomains_file.clone()))
+        .expect("target is required without a subcommand");
+    let output_file = args.output_file;
+    let mut formats = vec![];
+    for format in args.output_format {
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    if args.low_memory && formats != [OutputFormat::Json] {
+        bail!("--low-memory streams its output as JSON, it can't write {:?}", formats);
+    }
+    // A database is updated in place rather than rewritten, so it goes through --sqlite instead of the writers.
+    let sqlite_path = match formats.iter().position(|&format| format == OutputFormat::Sqlite) {
+        Some(index) => {
+            formats.remove(index);
+            args.sqlite.or_else(|| Some(OutputFormat::Sqlite.derived_path(&output_file)))
+        }
+        None => args.sqlite,
+    };
+    let metadata = ScanMetadata::new(args.scan_id, args.tag, &target);
+    info!("Scan id: {}", metadata.scan_id);
+    // Read before the scan, since chunk flushes rewrite the output file.
+    let json_file = match formats.as_slice() {
+        [OutputFormat::Json] => Some(output_file.clone()),
+        formats if formats.contains(&OutputFormat::Json) => Some(OutputFormat::Json.derived_path(&output_file)),
+        _ => None,
+    };
+    let baseline = args.baseline.as_deref().map(diff::load).transpose()?;
+    let first_seen = args.timestamp_findings.then(|| json_file.as_deref().map_or_else(FirstSeen::default, FirstSeen::load));
+
+    let mut root_domain = match target.parse::<IpAddr>() {
+        _ if args.targets_file.is_some() => {
+            let targets = targets::load(&target)?;
+            info!("Connect-scanning {} targets from {} without enumerating", targets.len(), target);
+            scan_targets(&config, &target, targets).await?
+        }
+        Ok(ip) => {
+            info!("Target is an IP address, inspecting {} without a wordlist", ip);
+            scan_range(&config, Cidr::from(ip)).await?
+        }
+        Err(_) if target.contains('/') && !config.target_is_label => {
+            let range: Cidr = target.parse()?;
+            check_range_size(range, args.max_range_size, args.force_large_range)?;
+            info!("Target is a network, inspecting the {} addresses of {} without a wordlist", range.size(), range);
+            scan_range(&config, range).await?
+        }
+        Err(_) => {
+            let subdomains = if args.no_wordlist {
+                info!("Resolving only {} itself, without a wordlist", target);
+                vec![]
+            } else {
+                read_wordlist(&args.subdomains_file, args.delimiter)?
+            };
+
+            if !args.fqdn {
+                warn_overlong_names(&target, &subdomains);
+            }
+
+            match chunk_size {
+                Some(chunk_size) if args.low_memory => {
+                    // Written as the chunks finish, so there's nothing left to write afterwards.
+                    let stream = (!args.count_only).then(|| JsonStream::create(&output_file, compression, &target)).transpose()?;
+                    let found = stream_chunks(&config, &target, subdomains, chunk_size as usize, stream, &metadata, first_seen.as_ref()).await?;
+                    if args.count_only {
+                        println!("{}", found);
+                    }
+
+                    return Ok(());
+                }
+                Some(chunk_size) => {
+                    // Nothing is written with --count-only, so there is nothing to flush between chunks.
+                    let flush = (!args.count_only).then_some(ChunkOutput {
+                        formats: &formats,
+                        output_file: &output_file,
+                        compression,
+                        metadata: &metadata,
+                        first_seen: first_seen.as_ref(),
+                    });
+                    scan_chunks(&config, &target, subdomains, chunk_size as usize, flush).await?
+                }
+                None => scan(&config, &target, subdomains, !args.count_only).await?,
+            }
+        }
+    };
+
+    let started = metadata.timestamp;
+    if !args.count_only {
+        root_domain.metadata = Some(metadata);
+        if let Some(first_seen) = &first_seen {
+            first_seen.stamp(&mut root_domain, started);
+
+            let missing = first_seen.missing(&root_domain);
+            if missing > 0 {
+                info!("{} subdomains of the earlier result were not found this time", missing);
+            }
+        }
+
+        // Records everything found, before --baseline narrows the result down.
+        if let Some(path) = &sqlite_path {
+            sqlite::write(path, &root_domain, started)?;
+        }
+    }
+
+    if let (Some(baseline), false) = (&baseline, args.diff) {
+        let found = root_domain.subdomain_count;
+        diff::only_new(&mut root_domain, baseline);
+        info!("{} of {} subdomains are new or have new addresses since the baseline", root_domain.subdomain_count, found);
+    }
+
+    if args.count_only {
+        println!("{}", root_domain.subdomain_count);
+        return Ok(());
+    }
+
+    if let (Some(baseline), true) = (&baseline, args.diff) {
+        let diff = diff::diff(baseline, &root_domain);
+        diff.print_summary();
+
+        fs::write(&output_file, serde_json::to_string(&diff)?)?;
+        info!("Wrote diff to {}", output_file);
+
+        return Ok(());
+    }
+
+    match formats.as_slice() {
+        [OutputFormat::Table] => {
+            OutputFormat::Table.writer().write(&root_domain, &mut std::io::stdout().lock())?;
+        }
+        [OutputFormat::Json] => {
+            info!("JSON: {}", serde_json::to_string(&root_domain)?);
+
+            write_output_file(&root_domain, OutputFormat::Json, &output_file, compression);
+        }
+        formats => write_output_files(&root_domain, formats, &output_file, compression),
+    }
 
     Ok(())
 }
+
+/// Reads the subdomains file as one entry per line, also split at `delimiter` when given.
+fn read_wordlist(path: &str, delimiter: Option<char>) -> Result<Vec<String>> {
+    let file_subdomains = fs::File::open(path)
+        .with_context(|| format!("Couldn't read subdomains file {}; pass --no-wordlist to scan only the target", path))?;
+    let mut reader = std::io::BufReader::new(file_subdomains);
+
+    let subdomains = match delimiter {
+        Some(delimiter) => {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents).with_context(|| format!("Couldn't read subdomains file {}", path))?;
+            contents.split([delimiter, '\n']).map(str::to_string).collect()
+        }
+        None => reader
+            .lines()
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("Couldn't read subdomains file {}", path))?,
+    };
+
+    Ok(subdomains)
+}
+
+/// Files `scan_chunks` rewrites after every chunk.
+struct ChunkOutput<'a> {
+    formats: &'a [OutputFormat],
+    output_file: &'a str,
+    compression: Option<Compression>,
+    metadata: &'a ScanMetadata,
+    first_seen: Option<&'a FirstSeen>,
+}
+
+/// Scans `subdomains` `chunk_size` names at a time, merging each chunk into the result. The
+/// target is resolved and probed once, and every chunk reuses that setup. With `flush`, the
+/// file outputs are rewritten after every chunk, so a crash loses at most one chunk. A table
+/// printed to stdout is only written once, at the end.
+async fn scan_chunks(
+    config: &ScanConfig,
+    target: &str,
+    subdomains: Vec<String>,
+    chunk_size: usize,
+    flush: Option<ChunkOutput<'_>>,
+) -> Result<RootDomain> {
+    let subdomains = dedup_wordlist(subdomains);
+    let prepared = TargetScan::prepare(config, target).await?;
+    let chunk_count = subdomains.len().div_ceil(chunk_size).max(1);
+    let progress = ScanProgress::start(config, target, &subdomains, Some(chunk_count), flush.is_some());
+    let mut root_domain: Option<RootDomain> = None;
+
+    for (index, chunk) in subdomains.chunks(chunk_size).enumerate() {
+        let started = Instant::now();
+        progress.start_chunk(index);
+        let chunk_domain = scan_names(config, target, &prepared, &progress, chunk.to_vec()).await?;
+        let found = chunk_domain.subdomain_count;
+        let mut merged = match root_domain.take() {
+            Some(mut merged) => {
+                merged.merge(chunk_domain);
+                merged
+            }
+            None => chunk_domain,
+        };
+
+        info!(
+            "Chunk {}/{}: {} names in {:.1?}, found {} subdomains ({} so far)",
+            index + 1, chunk_count, chunk.len(), started.elapsed(), found, merged.subdomain_count,
+        );
+
+        if let Some(flush) = &flush {
+            merged.metadata = Some(flush.metadata.clone());
+            if let Some(first_seen) = flush.first_seen {
+                first_seen.stamp(&mut merged, flush.metadata.timestamp);
+            }
+            match flush.formats {
+                [OutputFormat::Table] => {}
+                [format] => write_output_file(&merged, *format, flush.output_file, flush.compression),
+                formats => write_output_files(&merged, formats, flush.output_file, flush.compression),
+            }
+        }
+
+        // A quit from the TUI, or the first match, stops the remaining chunks too.
+        let done = progress.is_cancelled() || (config.first_match && merged.subdomain_count > 0);
+        root_domain = Some(merged);
+        if done {
+            break;
+        }
+    }
+
+    let mut root_domain = match root_domain {
+        Some(root_domain) => root_domain,
+        // An empty wordlist still resolves the target itself.
+        None => scan_names(config, target, &prepared, &progress, vec![]).await?,
+    };
+    progress.finish().await?;
+    finish_scan(config, &mut root_domain).await;
+
+    Ok(root_domain)
+}
+
+/// Like `scan_chunks` with `--low-memory`: each chunk's findings are appended to `stream` and
+/// dropped, so memory stays flat however many names are found. Only the totals and the set of
+/// distinct addresses are kept. Returns how many subdomains were found.
+async fn stream_chunks(
+    config: &ScanConfig,
+    target: &str,
+    subdomains: Vec<String>,
+    chunk_size: usize,
+    mut stream: Option<JsonStream>,
+    metadata: &ScanMetadata,
+    first_seen: Option<&FirstSeen>,
+) -> Result<usize> {
+    let subdomains = dedup_wordlist(subdomains);
+    let prepared = TargetScan::prepare(config, target).await?;
+    let chunk_count = subdomains.len().div_ceil(chunk_size).max(1);
+    let progress = ScanProgress::start(config, target, &subdomains, Some(chunk_count), stream.is_some());
+    let mut root_domain: Option<RootDomain> = None;
+    let (mut found, mut total_addresses) = (0, 0);
+    let mut ips = HashSet::new();
+
+    for (index, chunk) in subdomains.chunks(chunk_size).enumerate() {
+        let started = Instant::now();
+        progress.start_chunk(index);
+        let mut chunk_domain = scan_names(config, target, &prepared, &progress, chunk.to_vec()).await?;
+        if let Some(first_seen) = first_seen {
+            first_seen.stamp(&mut chunk_domain, metadata.timestamp);
+        }
+        if let Some(stream) = &mut stream {
+            stream.append(&chunk_domain.subdomains)?;
+        }
+        let chunk_found = chunk_domain.subdomains.len();
+        for subdomain in chunk_domain.subdomains.drain(..) {
+            total_addresses += subdomain.addresses.len();
+            ips.extend(subdomain.addresses.iter().map(|address| address.ip));
+        }
+        found += chunk_found;
+
+        info!(
+            "Chunk {}/{}: {} names in {:.1?}, found {} subdomains ({} so far)",
+            index + 1, chunk_count, chunk.len(), started.elapsed(), chunk_found, found,
+        );
+
+        root_domain = Some(match root_domain.take() {
+            Some(mut merged) => {
+                merged.merge(chunk_domain);
+                merged
+            }
+            None => chunk_domain,
+        });
+        if progress.is_cancelled() || (config.first_match && found > 0) {
+            break;
+        }
+    }
+
+    let mut root_domain = match root_domain {
+        Some(root_domain) => root_domain,
+        None => scan_names(config, target, &prepared, &progress, vec![]).await?,
+    };
+    progress.finish().await?;
+    if let Some(stream) = stream {
+        // The root entry only counted its own addresses, the streamed names are added here.
+        root_domain.summarize();
+        ips.extend(root_domain.addresses.iter().map(|address| address.ip));
+        root_domain.subdomain_count = found;
+        root_domain.total_addresses += total_addresses;
+        root_domain.unique_ip_count = ips.len();
+        root_domain.metadata = Some(metadata.clone());
+        let path = stream.path().to_string();
+        stream.finish(&root_domain)?;
+        info!("Wrote {:?} output to {}", OutputFormat::Json, path);
+    }
+
+    Ok(found)
+}
+
+/// Drops repeated wordlist entries up front, since chunks are only deduplicated one at a time
+/// and a name listed in two chunks would otherwise be queried and found twice.
+fn dedup_wordlist(subdomains: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    subdomains.into_iter().filter(|line| seen.insert(line.trim().to_lowercase())).collect()
+}
+
+/// Writes each of `formats` to its own file, named after `output_file` with the format's extension.
+fn write_output_files(root_domain: &RootDomain, formats: &[OutputFormat], output_file: &str, compression: Option<Compression>) {
+    for &format in formats {
+        write_output_file(root_domain, format, &format.derived_path(output_file), compression);
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so a reader or a
+/// crash mid-write never sees a half written file.
+fn write_output_file(root_domain: &RootDomain, format: OutputFormat, path: &str, compression: Option<Compression>) {
+    let partial = format!("{}.partial", path);
+    let file = fs::File::create(&partial).expect("Could not create output file");
+    output::write_compressed(format.writer().as_ref(), root_domain, std::io::BufWriter::new(file), compression)
+        .expect("Could not write output");
+    fs::rename(&partial, path).expect("Could not move output file into place");
+
+    info!("Wrote {:?} output to {}", format, path);
+}
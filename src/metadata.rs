@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Describes the run that produced a result, so results stored together stay self-describing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanMetadata {
+    pub scan_id: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    pub target: String,
+    /// When the scan started.
+    pub timestamp: DateTime<Utc>,
+    pub version: String,
+}
+
+impl ScanMetadata {
+    /// Metadata for a scan of `target` starting now, with a random UUID when `scan_id` is `None`.
+    pub fn new(scan_id: Option<String>, tags: Vec<(String, String)>, target: &str) -> Self {
+        ScanMetadata {
+            scan_id: scan_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            tags: tags.into_iter().collect(),
+            target: target.to_string(),
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("invalid tag {}, expected key=value", tag)),
+    }
+}
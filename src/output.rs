@@ -0,0 +1,288 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use comfy_table::{presets::UTF8_FULL, Table};
+use flate2::write::GzEncoder;
+
+use crate::{Address, RootDomain, Subdomain};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Table,
+    Hosts,
+    Sqlite,
+}
+
+/// Renders a finished scan in one output format.
+pub trait OutputWriter {
+    fn write(&self, root_domain: &RootDomain, writer: &mut dyn Write) -> Result<()>;
+}
+
+impl OutputFormat {
+    pub fn writer(self) -> Box<dyn OutputWriter> {
+        match self {
+            OutputFormat::Json => Box::new(JsonWriter),
+            OutputFormat::Table => Box::new(TableWriter),
+            OutputFormat::Hosts => Box::new(HostsWriter),
+            OutputFormat::Sqlite => unreachable!("SQLite output is written through --sqlite"),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Table => "txt",
+            OutputFormat::Hosts => "hosts",
+            OutputFormat::Sqlite => "db",
+        }
+    }
+
+    /// File this format is written to when several formats share one `--output-file`: the
+    /// output file with its extension swapped, keeping a trailing `.gz` or `.zst` except for a
+    /// SQLite database, which is never compressed.
+    pub fn derived_path(self, output_file: &str) -> String {
+        let (base, compressed) = match Compression::from_path(output_file) {
+            Some(compression) => output_file.split_at(output_file.len() - compression.extension().len() - 1),
+            None => (output_file, ""),
+        };
+        let compressed = if self == OutputFormat::Sqlite { "" } else { compressed };
+
+        format!("{}{}", Path::new(base).with_extension(self.extension()).display(), compressed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Picks the compression implied by a `.gz` or `.zst` output file name.
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.ends_with(".gz") {
+            Some(Compression::Gzip)
+        } else if path.ends_with(".zst") {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Recognizes gzip and zstd data from its leading magic bytes.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// Renders `root_domain` with `format` into `writer`, compressed when `compression` is set.
+/// The compressed stream is finished explicitly so a failed trailer write is reported.
+pub fn write_compressed(
+    format: &dyn OutputWriter,
+    root_domain: &RootDomain,
+    writer: impl Write,
+    compression: Option<Compression>,
+) -> Result<()> {
+    match compression {
+        None => {
+            let mut writer = writer;
+            format.write(root_domain, &mut writer)?;
+            writer.flush()?;
+        }
+        Some(Compression::Gzip) => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            format.write(root_domain, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Some(Compression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            format.write(root_domain, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a JSON result while the scan runs, so found names can be dropped as soon as they are
+/// written. The file ends up as the object `JsonWriter` writes, with `subdomains` first and the
+/// root entry and totals after it. It's written to `<path>.partial` and moved into place by
+/// `finish`, so a crash leaves everything written so far there.
+pub struct JsonStream {
+    path: String,
+    partial: String,
+    encoder: Encoder,
+    written: usize,
+}
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Zstd(encoder) => encoder,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(mut writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.finish()?.flush(),
+            Encoder::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl JsonStream {
+    pub fn create(path: &str, compression: Option<Compression>, name: &str) -> Result<Self> {
+        let partial = format!("{}.partial", path);
+        let file = BufWriter::new(File::create(&partial).with_context(|| format!("Couldn't create output file {}", partial))?);
+        let encoder = match compression {
+            None => Encoder::Plain(file),
+            Some(Compression::Gzip) => Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Some(Compression::Zstd) => Encoder::Zstd(zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+        };
+        let mut stream = JsonStream { path: path.to_string(), partial, encoder, written: 0 };
+        write!(stream.encoder.writer(), "{{\"name\":{},\"subdomains\":[", serde_json::to_string(name)?)?;
+
+        Ok(stream)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes `subdomains` and flushes them, compressed ones included, through to the file.
+    pub fn append(&mut self, subdomains: &[Subdomain]) -> Result<()> {
+        let writer = self.encoder.writer();
+        for subdomain in subdomains {
+            if self.written > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *writer, subdomain)?;
+            self.written += 1;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Closes the list and writes every other field of `root_domain`, whose own subdomains are
+    /// ignored, then moves the file into place.
+    pub fn finish(mut self, root_domain: &RootDomain) -> Result<()> {
+        let mut fields = serde_json::to_value(root_domain)?;
+        let fields = fields.as_object_mut().context("result didn't serialize to an object")?;
+        fields.remove("name");
+        fields.remove("subdomains");
+
+        let writer = self.encoder.writer();
+        writer.write_all(b"]")?;
+        for (key, value) in fields.iter() {
+            write!(writer, ",{}:{}", serde_json::to_string(key)?, value)?;
+        }
+        writer.write_all(b"}")?;
+        self.encoder.finish()?;
+        fs::rename(&self.partial, &self.path).with_context(|| format!("Couldn't move output file to {}", self.path))?;
+
+        Ok(())
+    }
+}
+
+pub struct JsonWriter;
+
+impl OutputWriter for JsonWriter {
+    fn write(&self, root_domain: &RootDomain, writer: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *writer, root_domain)?;
+
+        Ok(())
+    }
+}
+
+/// One row per (name, address) pair, with the open ports of that address.
+pub struct TableWriter;
+
+impl OutputWriter for TableWriter {
+    fn write(&self, root_domain: &RootDomain, writer: &mut dyn Write) -> Result<()> {
+        let mut table = Table::new();
+        table.load_preset(UTF8_FULL).set_header(vec!["Name", "Address", "Open ports"]);
+
+        let names = std::iter::once((&root_domain.name, &root_domain.addresses))
+            .chain(root_domain.subdomains.iter().map(|subdomain| (&subdomain.name, &subdomain.addresses)));
+
+        for (name, addresses) in names {
+            if addresses.is_empty() {
+                table.add_row(vec![name.clone(), String::new(), String::new()]);
+            }
+
+            for address in addresses {
+                table.add_row(vec![name.clone(), address.ip.to_string(), open_ports(address)]);
+            }
+        }
+
+        writeln!(writer, "{}", table)?;
+
+        Ok(())
+    }
+}
+
+/// `/etc/hosts` lines, one `ip name` per (address, name) pair. A target that is an address or
+/// a network has no name of its own, so its addresses are listed under their PTR names.
+pub struct HostsWriter;
+
+impl OutputWriter for HostsWriter {
+    fn write(&self, root_domain: &RootDomain, writer: &mut dyn Write) -> Result<()> {
+        writeln!(writer, "# {}", root_domain.name)?;
+
+        let root_is_name = root_domain.name.parse::<IpAddr>().is_err() && !root_domain.name.contains('/');
+        for address in &root_domain.addresses {
+            if root_is_name {
+                writeln!(writer, "{}\t{}", address.ip, root_domain.name)?;
+            } else {
+                for name in &address.ptr {
+                    writeln!(writer, "{}\t{}", address.ip, name.trim_end_matches('.'))?;
+                }
+            }
+        }
+
+        for subdomain in &root_domain.subdomains {
+            for address in &subdomain.addresses {
+                writeln!(writer, "{}\t{}", address.ip, subdomain.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn open_ports(address: &Address) -> String {
+    address.ports
+        .iter()
+        .filter(|port| port.is_open())
+        .map(|port| port.port.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
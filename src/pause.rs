@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::watch;
+#[cfg(unix)]
+use tracing::info;
+
+/// Scan-wide pause flag. Workers wait on it before each query, so a paused scan finishes the
+/// queries already in flight and then idles until it is resumed.
+#[derive(Debug)]
+pub struct PauseSwitch {
+    paused: watch::Sender<bool>,
+}
+
+impl PauseSwitch {
+    pub fn new() -> Self {
+        PauseSwitch { paused: watch::channel(false).0 }
+    }
+
+    /// Flips the flag and returns whether the scan is now paused.
+    pub fn toggle(&self) -> bool {
+        self.paused.send_modify(|paused| *paused = !*paused);
+        *self.paused.borrow()
+    }
+
+    /// Returns once the scan is not paused.
+    pub async fn wait(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender lives in `self`, so the channel can't close while we wait.
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+}
+
+/// Toggles `switch` on every SIGUSR1, e.g. `kill -USR1 <pid>`.
+#[cfg(unix)]
+pub fn listen(switch: Arc<PauseSwitch>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::user_defined1())?;
+
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            if switch.toggle() {
+                info!("Paused, send SIGUSR1 again to resume");
+            } else {
+                info!("Resumed");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(_switch: Arc<PauseSwitch>) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use futures::future::join_all;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tracing::debug;
+#[cfg(all(unix, feature = "raw-socket"))]
+use tracing::warn;
+
+use crate::interface;
+
+// File descriptors kept back from `--max-open-sockets` for stdio, the output file and the runtime.
+const RESERVED_FDS: u64 = 64;
+// Enough for a greeting line; a service that streams data is never read further.
+const MAX_BANNER_LEN: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Port {
+    pub port: u16,
+    /// Results written before states were recorded only list open ports.
+    #[serde(default)]
+    pub state: PortState,
+    /// URLs the site on this port redirected to, in order, with `--follow-redirects`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub http_redirects: Vec<String>,
+    /// First line the service sent right after the connect, with `--banners`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+}
+
+impl Port {
+    pub fn new(port: u16, state: PortState) -> Self {
+        Port { port, state, http_redirects: vec![], banner: None }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == PortState::Open
+    }
+
+    /// Open, or open|filtered from a scan that can't tell the two apart. Results keep these
+    /// without `--port-states`.
+    pub fn may_be_open(&self) -> bool {
+        matches!(self.state, PortState::Open | PortState::OpenFiltered)
+    }
+}
+
+/// What a connect or raw probe learned about a port, in nmap's terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortState {
+    /// The connection was accepted.
+    #[default]
+    Open,
+    /// The host answered with a RST.
+    Closed,
+    /// No answer before the timeout, or an ICMP unreachable: something dropped the probe.
+    Filtered,
+    /// No answer to a FIN, NULL or Xmas probe. Only closed ports must answer those, so the port
+    /// is open or something dropped the probe.
+    #[serde(rename = "open|filtered")]
+    OpenFiltered,
+}
+
+impl std::fmt::Display for PortState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::OpenFiltered => "open|filtered",
+        })
+    }
+}
+
+impl PortState {
+    fn of_connect(result: Result<io::Result<tokio::net::TcpStream>, tokio::time::error::Elapsed>) -> Self {
+        match result {
+            Ok(Ok(_)) => PortState::Open,
+            Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => PortState::Closed,
+            Ok(Err(_)) | Err(_) => PortState::Filtered,
+        }
+    }
+}
+
+/// How ports are probed. Everything but `Connect` crafts raw TCP packets, which needs the
+/// `raw-socket` feature and root or CAP_NET_RAW.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScanType {
+    Connect,
+    Syn,
+    Fin,
+    Null,
+    Xmas,
+}
+
+/// Fails when `scan_type` can't run in this build or with this process's privileges.
+pub fn check_scan_type(scan_type: ScanType) -> Result<()> {
+    if scan_type == ScanType::Connect {
+        return Ok(());
+    }
+
+    check_raw_sockets()
+}
+
+#[cfg(all(unix, feature = "raw-socket"))]
+fn check_raw_sockets() -> Result<()> {
+    crate::raw_scan::check()
+}
+
+#[cfg(not(all(unix, feature = "raw-socket")))]
+fn check_raw_sockets() -> Result<()> {
+    bail!("Raw socket scan types need a unix build with the raw-socket feature")
+}
+
+/// The 100 TCP ports most often found open, as ranked by nmap's services frequency list.
+pub const TOP_100_PORTS: [u16; 100] = [
+    7, 9, 13, 21, 22, 23, 25, 26, 37, 53, 79, 80, 81, 88, 106, 110, 111, 113, 119, 135, 139, 143, 144, 179, 199,
+    389, 427, 443, 444, 445, 465, 513, 514, 515, 543, 544, 548, 554, 587, 631, 646, 873, 990, 993, 995, 1025, 1026,
+    1027, 1028, 1029, 1110, 1433, 1720, 1723, 1755, 1900, 2000, 2001, 2049, 2121, 2717, 3000, 3128, 3306, 3389,
+    3986, 4899, 5000, 5009, 5051, 5060, 5101, 5190, 5357, 5432, 5631, 5666, 5800, 5900, 6000, 6001, 6646, 7070,
+    8000, 8008, 8009, 8080, 8081, 8443, 8888, 9100, 9999, 10000, 32768, 49152, 49153, 49154, 49155, 49156, 49157,
+];
+
+/// Parses a port list such as `22,80,8000-8100` into sorted, deduplicated ports.
+pub fn parse_ports(ports: &str) -> Result<Vec<u16>> {
+    let mut parsed = vec![];
+
+    for part in ports.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: u16 = start.trim().parse().with_context(|| format!("Invalid port range {}", part))?;
+                let end: u16 = end.trim().parse().with_context(|| format!("Invalid port range {}", part))?;
+                if start > end {
+                    bail!("Invalid port range {}", part);
+                }
+                parsed.extend(start..=end);
+            }
+            None => {
+                parsed.push(part.parse().with_context(|| format!("Invalid port {}", part))?);
+            }
+        }
+    }
+
+    parsed.sort_unstable();
+    parsed.dedup();
+
+    Ok(parsed)
+}
+
+/// Formats sorted `ports` the way `parse_ports` reads them, with consecutive ports as ranges.
+pub fn format_ports(ports: &[u16]) -> String {
+    let mut ranges: Vec<(u16, u16)> = vec![];
+
+    for &port in ports {
+        match ranges.last_mut() {
+            Some((_, end)) if u32::from(*end) + 1 == u32::from(port) => *end = port,
+            _ => ranges.push((port, port)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Default cap on concurrently open sockets, derived from the process open file limit.
+pub fn default_max_open_sockets() -> usize {
+    let limit = open_file_limit().unwrap_or(1024);
+
+    limit.saturating_sub(RESERVED_FDS).clamp(1, Semaphore::MAX_PERMITS as u64) as usize
+}
+
+#[cfg(unix)]
+fn open_file_limit() -> Option<u64> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+
+    // SAFETY: getrlimit only writes into the rlimit struct we pass in.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0 {
+        #[allow(clippy::unnecessary_cast)]
+        Some(limit.rlim_cur as u64)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn open_file_limit() -> Option<u64> {
+    None
+}
+
+/// Caps concurrent connects to any single address, however many names resolve to it and
+/// however high the global limits are. Without a cap it never waits.
+#[derive(Debug)]
+pub struct HostLimits {
+    max_per_host: Option<usize>,
+    hosts: Mutex<HashMap<IpAddr, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    pub fn new(max_per_host: Option<usize>) -> Self {
+        HostLimits { max_per_host, hosts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Semaphore shared by every probe of `ip`, or `None` without a cap.
+    fn host(&self, ip: IpAddr) -> Option<HostSlot<'_>> {
+        let max_per_host = self.max_per_host?;
+        let mut hosts = self.hosts.lock().expect("host limits lock poisoned");
+        let semaphore = Arc::clone(hosts.entry(ip).or_insert_with(|| Arc::new(Semaphore::new(max_per_host))));
+
+        Some(HostSlot { limits: self, ip, semaphore })
+    }
+}
+
+/// A scan's hold on its host's semaphore. The last one released removes the host, so the map
+/// only holds hosts being scanned rather than every host of a large range.
+struct HostSlot<'a> {
+    limits: &'a HostLimits,
+    ip: IpAddr,
+    semaphore: Arc<Semaphore>,
+}
+
+impl Drop for HostSlot<'_> {
+    fn drop(&mut self) {
+        let mut hosts = self.limits.hosts.lock().expect("host limits lock poisoned");
+        // Held by the map and this slot only; new slots are only handed out under the lock.
+        if Arc::strong_count(&self.semaphore) == 2 {
+            hosts.remove(&self.ip);
+        }
+    }
+}
+
+/// Probes each host's ports in a shuffled order instead of ascending. With a seed, each host's
+/// order is derived from the seed and its address, so a rerun probes in the same order.
+#[derive(Debug, Clone, Copy)]
+pub struct PortShuffle {
+    pub seed: Option<u64>,
+}
+
+impl PortShuffle {
+    fn order(self, ip: IpAddr, ports: &[u16]) -> Vec<u16> {
+        let mut ports = ports.to_vec();
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ ip_bits(ip)),
+            None => StdRng::from_entropy(),
+        };
+        ports.shuffle(&mut rng);
+
+        ports
+    }
+}
+
+fn ip_bits(ip: IpAddr) -> u64 {
+    match ip {
+        IpAddr::V4(ip) => u64::from(u32::from(ip)),
+        IpAddr::V6(ip) => {
+            let bits = u128::from(ip);
+            (bits >> 64) as u64 ^ bits as u64
+        }
+    }
+}
+
+/// Scans `ports` on `ip` with `scan_type`, returning the ports that may be open, or with
+/// `all_states` every port with the state it was classified in. With `banners`, connect scans
+/// keep the first line each open port sends. Each probe holds
+/// a `scan_slots` permit as well as a `sockets` one, so port scanning has its own parallelism cap
+/// under the process-wide socket budget. Connect probes also wait for `host_limits`. Ports are
+/// returned in ascending order whatever order `shuffle` probed them in.
+#[allow(clippy::too_many_arguments)]
+pub async fn scan_ports(ip: IpAddr, ports: &[u16], connect_timeout: Duration, sockets: &Semaphore, scan_slots: &Semaphore, host_limits: &HostLimits, scan_type: ScanType, shuffle: Option<PortShuffle>, all_states: bool, banners: bool) -> Vec<Port> {
+    let shuffled;
+    let ports = match shuffle {
+        Some(shuffle) => {
+            shuffled = shuffle.order(ip, ports);
+            &shuffled
+        }
+        None => ports,
+    };
+
+    let mut probed = match scan_type {
+        ScanType::Connect => connect_scan(ip, ports, connect_timeout, sockets, scan_slots, host_limits, banners).await,
+        _ => raw_scan(ip, ports, connect_timeout, sockets, scan_slots, scan_type).await,
+    };
+    let count = |state| probed.iter().filter(|port| port.state == state).count();
+    debug!(
+        "{}: {} open, {} closed, {} filtered, {} open|filtered",
+        ip, count(PortState::Open), count(PortState::Closed), count(PortState::Filtered), count(PortState::OpenFiltered),
+    );
+    if !all_states {
+        probed.retain(Port::may_be_open);
+    }
+    probed.sort_unstable_by_key(|port| port.port);
+
+    probed
+}
+
+#[cfg(all(unix, feature = "raw-socket"))]
+async fn raw_scan(ip: IpAddr, ports: &[u16], wait: Duration, sockets: &Semaphore, scan_slots: &Semaphore, scan_type: ScanType) -> Vec<Port> {
+    let IpAddr::V4(ip) = ip else {
+        warn!("Skipping {}, raw socket scans are IPv4 only", ip);
+        return vec![];
+    };
+    let _slot = scan_slots.acquire().await.expect("scan semaphore closed");
+    let _permit = sockets.acquire().await.expect("socket semaphore closed");
+    let ports = ports.to_vec();
+
+    match tokio::task::spawn_blocking(move || crate::raw_scan::scan(ip, &ports, scan_type, wait)).await {
+        Ok(Ok(probed)) => probed,
+        Ok(Err(err)) => {
+            warn!("{:?} scan of {} failed: {:#}", scan_type, ip, err);
+            vec![]
+        }
+        Err(err) => {
+            warn!("{:?} scan of {} panicked: {}", scan_type, ip, err);
+            vec![]
+        }
+    }
+}
+
+#[cfg(not(all(unix, feature = "raw-socket")))]
+async fn raw_scan(_ip: IpAddr, _ports: &[u16], _wait: Duration, _sockets: &Semaphore, _scan_slots: &Semaphore, _scan_type: ScanType) -> Vec<Port> {
+    unreachable!("raw scan types are rejected by check_scan_type in this build")
+}
+
+/// TCP connect scan of `ports` on `ip`, returning every port with the state its connect ended in.
+/// Every connect holds a `sockets` permit, so scans wait rather than run out of descriptors.
+/// The per-host permit is taken first so a throttled host doesn't sit on global slots.
+async fn connect_scan(ip: IpAddr, ports: &[u16], connect_timeout: Duration, sockets: &Semaphore, scan_slots: &Semaphore, host_limits: &HostLimits, banners: bool) -> Vec<Port> {
+    let host = host_limits.host(ip);
+    let host = &host;
+    let probes = ports.iter().map(|&port| async move {
+        let _host = match host {
+            Some(host) => Some(host.semaphore.acquire().await.expect("host semaphore closed")),
+            None => None,
+        };
+        let _slot = scan_slots.acquire().await.expect("scan semaphore closed");
+        let _permit = sockets.acquire().await.expect("socket semaphore closed");
+
+        let mut connected = timeout(connect_timeout, interface::connect_tcp(SocketAddr::new(ip, port))).await;
+        let banner = match &mut connected {
+            Ok(Ok(stream)) if banners => read_banner(stream, connect_timeout).await,
+            _ => None,
+        };
+
+        Port { banner, ..Port::new(port, PortState::of_connect(connected)) }
+    });
+
+    join_all(probes).await
+}
+
+/// What a server-first service such as SSH, SMTP or FTP sends right after the connect, up to
+/// the first line break and without control characters. Services that wait for the client to
+/// speak first send nothing within `wait` and get no banner.
+async fn read_banner(stream: &mut TcpStream, wait: Duration) -> Option<String> {
+    let mut buf = [0; MAX_BANNER_LEN];
+    let read = timeout(wait, stream.read(&mut buf)).await.ok()?.ok()?;
+    let text = String::from_utf8_lossy(&buf[..read]);
+    let line: String = text.lines().next()?.chars().filter(|c| !c.is_control()).collect();
+    let line = line.trim();
+
+    (!line.is_empty()).then(|| line.to_string())
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::ports;
+
+/// Preset for several flags at once. Every value is only a default, so a flag given on the
+/// command line still wins over the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Many workers, a short timeout and the 100 most common ports.
+    Fast,
+    /// Fewer workers, a long timeout, retries, every port and banner grabbing.
+    Thorough,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileDefaults {
+    pub concurrency: u8,
+    pub timeout: Duration,
+    pub ports: Vec<u16>,
+    /// `None` keeps the usual default of one retry per extra resolver.
+    pub retries: Option<usize>,
+    pub banners: bool,
+}
+
+impl Profile {
+    pub fn from_flags(fast: bool, thorough: bool) -> Option<Self> {
+        match (fast, thorough) {
+            (true, _) => Some(Profile::Fast),
+            (_, true) => Some(Profile::Thorough),
+            _ => None,
+        }
+    }
+
+    pub fn defaults(self) -> ProfileDefaults {
+        match self {
+            Profile::Fast => ProfileDefaults {
+                concurrency: 100,
+                timeout: Duration::from_millis(500),
+                ports: ports::TOP_100_PORTS.to_vec(),
+                retries: Some(0),
+                banners: false,
+            },
+            Profile::Thorough => ProfileDefaults {
+                concurrency: 16,
+                timeout: Duration::from_secs(3),
+                ports: (1..=u16::MAX).collect(),
+                retries: Some(2),
+                banners: true,
+            },
+        }
+    }
+}
+
+impl Default for ProfileDefaults {
+    /// Settings used without a profile.
+    fn default() -> Self {
+        ProfileDefaults {
+            concurrency: 1,
+            timeout: Duration::from_secs(1),
+            ports: vec![],
+            retries: None,
+            banners: false,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use serde::Serialize;
+use tracing::warn;
+
+/// One `--progress-file` snapshot, read off the progress bar and the scan's counters.
+#[derive(Debug, Serialize)]
+pub struct Progress<'a> {
+    pub target: &'a str,
+    /// Names resolved so far, and all names queued, including those discovered mid-scan. With
+    /// `--chunk-size` both count the whole wordlist, not the current chunk.
+    pub done: u64,
+    pub total: u64,
+    pub percent: f64,
+    /// Names resolved per second, averaged by the progress bar.
+    pub rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<u64>,
+    pub elapsed_secs: u64,
+    pub found: usize,
+    pub wildcards: usize,
+    /// The chunk being scanned, counted from 1, and how many there are, with `--chunk-size`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<usize>,
+    pub finished: bool,
+}
+
+impl<'a> Progress<'a> {
+    pub fn of(bar: &ProgressBar, target: &'a str, found: usize, wildcards: usize, chunk: Option<(usize, usize)>, finished: bool) -> Self {
+        let done = bar.position();
+        let total = bar.length().unwrap_or(done);
+        let percent = if total == 0 { 100.0 } else { done as f64 * 100.0 / total as f64 };
+
+        Progress {
+            target,
+            done,
+            total,
+            percent: (percent * 10.0).round() / 10.0,
+            rate: (bar.per_sec() * 10.0).round() / 10.0,
+            eta_secs: (!finished && done > 0).then(|| bar.eta().as_secs()),
+            elapsed_secs: bar.elapsed().as_secs(),
+            found,
+            wildcards,
+            chunk: chunk.map(|(chunk, _)| chunk),
+            chunks: chunk.map(|(_, chunks)| chunks),
+            finished,
+        }
+    }
+}
+
+/// Where `--progress-file` snapshots go. A regular file is replaced by each snapshot, so a
+/// reader always sees one complete JSON object; a named pipe gets one JSON line per snapshot.
+pub struct ProgressFile {
+    path: String,
+    pipe: Option<Mutex<fs::File>>,
+}
+
+impl ProgressFile {
+    /// Opening a named pipe waits for its reader, like any writer to a pipe.
+    pub fn open(path: &str) -> Result<Self> {
+        let pipe = match fs::metadata(path) {
+            #[cfg(unix)]
+            Ok(metadata) if metadata.file_type().is_fifo() => {
+                let file = fs::OpenOptions::new().write(true).open(path).with_context(|| format!("Couldn't open progress pipe {}", path))?;
+                Some(Mutex::new(file))
+            }
+            _ => None,
+        };
+
+        Ok(ProgressFile { path: path.to_string(), pipe })
+    }
+
+    /// Failures are logged and never stop the scan.
+    pub fn write(&self, progress: &Progress) {
+        let json = serde_json::to_string(progress).expect("Couldn't serialize progress");
+
+        let written = match &self.pipe {
+            Some(pipe) => writeln!(pipe.lock().expect("progress pipe poisoned"), "{}", json),
+            None => {
+                let partial = format!("{}.partial", self.path);
+                fs::write(&partial, format!("{}\n", json)).and_then(|()| fs::rename(&partial, &self.path))
+            }
+        };
+        if let Err(err) = written {
+            warn!("Couldn't write progress to {}: {}", self.path, err);
+        }
+    }
+}
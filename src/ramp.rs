@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Gate that lets workers start gradually. It opens with one permit and doubles the permits
+/// released in evenly spaced steps until `concurrency` workers can run after `window`, so a
+/// resolver sees the query rate grow instead of taking full load from the first query.
+pub struct RampUp {
+    permits: Arc<Semaphore>,
+    schedule: JoinHandle<()>,
+}
+
+impl RampUp {
+    pub fn start(concurrency: usize, window: Duration) -> Self {
+        let permits = Arc::new(Semaphore::new(1));
+        let steps = concurrency.next_power_of_two().trailing_zeros().max(1);
+        let interval = window / steps;
+
+        let schedule = {
+            let permits = Arc::clone(&permits);
+
+            tokio::spawn(async move {
+                let mut released = 1;
+
+                while released < concurrency {
+                    tokio::time::sleep(interval).await;
+
+                    let next = (released * 2).min(concurrency);
+                    permits.add_permits(next - released);
+                    released = next;
+                    debug!("Ramp-up: {} of {} workers running", released, concurrency);
+                }
+            })
+        };
+
+        RampUp { permits, schedule }
+    }
+
+    /// Waits until the schedule lets one more worker start, or the ramp-up is closed. The permit
+    /// is kept for the rest of the scan, since a running worker never gives its slot back.
+    pub async fn wait(&self) {
+        if let Ok(permit) = self.permits.acquire().await {
+            permit.forget();
+        }
+    }
+
+    /// Lets every waiting worker through at once, for when the queue is already drained and
+    /// they would only find it empty.
+    pub fn close(&self) {
+        self.permits.close();
+    }
+}
+
+impl Drop for RampUp {
+    fn drop(&mut self) {
+        self.schedule.abort();
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use rand::Rng;
+use socket2::SockRef;
+
+use crate::interface;
+use crate::ports::{Port, PortState, ScanType};
+
+const TCP_HEADER_LEN: usize = 20;
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+const URG: u8 = 0x20;
+
+// How long a single recv blocks before the deadline is checked again.
+const RECV_POLL: Duration = Duration::from_millis(100);
+
+/// Opens and closes a raw socket, so a missing privilege is reported before the scan starts.
+pub fn check() -> Result<()> {
+    open_raw_socket().map(drop)
+}
+
+/// Sends one crafted probe to each of `ports` on `ip` and classifies every port by the reply
+/// that arrives within `wait`. A RST means closed. A SYN scan reports SYN/ACK as open and
+/// anything else as filtered. FIN, NULL and Xmas scans report every other port as open|filtered,
+/// since only closed ports must reply to them.
+pub fn scan(ip: Ipv4Addr, ports: &[u16], scan_type: ScanType, wait: Duration) -> Result<Vec<Port>> {
+    let flags = match scan_type {
+        ScanType::Syn => SYN,
+        ScanType::Fin => FIN,
+        ScanType::Null => 0,
+        ScanType::Xmas => FIN | PSH | URG,
+        ScanType::Connect => bail!("connect scans don't use raw sockets"),
+    };
+
+    let source = source_address(ip)?;
+    let socket = open_raw_socket()?;
+    set_recv_timeout(&socket, RECV_POLL)?;
+
+    let mut rng = rand::thread_rng();
+    let source_port: u16 = rng.gen_range(40000..60000);
+    let sequence: u32 = rng.gen();
+
+    for &port in ports {
+        let segment = tcp_segment(source, ip, source_port, port, sequence, flags);
+        send_to(&socket, &segment, ip)?;
+    }
+
+    let mut replies: HashMap<u16, u8> = HashMap::new();
+    let deadline = Instant::now() + wait;
+    let mut buffer = [0u8; 1500];
+
+    while Instant::now() < deadline && replies.len() < ports.len() {
+        let len = match recv(&socket, &mut buffer) {
+            Ok(len) => len,
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => continue,
+            Err(err) => return Err(err).context("Couldn't read from raw socket"),
+        };
+
+        if let Some((port, flags)) = parse_reply(&buffer[..len], ip, source_port) {
+            if ports.contains(&port) {
+                replies.entry(port).or_insert(flags);
+            }
+        }
+    }
+
+    let state = |port| match (scan_type, replies.get(&port)) {
+        (ScanType::Syn, Some(flags)) if flags & (SYN | ACK) == SYN | ACK => PortState::Open,
+        (_, Some(flags)) if flags & RST != 0 => PortState::Closed,
+        (ScanType::Syn, _) => PortState::Filtered,
+        (_, _) => PortState::OpenFiltered,
+    };
+
+    Ok(ports.iter().map(|&port| Port::new(port, state(port))).collect())
+}
+
+fn open_raw_socket() -> Result<OwnedFd> {
+    // SAFETY: plain socket(2) call; the descriptor is owned by the OwnedFd from here on.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_TCP) };
+
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            bail!("Raw socket scans need root or CAP_NET_RAW ({})", err);
+        }
+        return Err(err).context("Couldn't open raw socket");
+    }
+
+    // SAFETY: fd was just returned by socket(2) and nothing else owns it.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    interface::apply(&SockRef::from(&socket)).context("Couldn't bind raw socket to interface")?;
+
+    Ok(socket)
+}
+
+/// Local address the kernel routes towards `ip` from, needed for the TCP checksum.
+fn source_address(ip: Ipv4Addr) -> Result<Ipv4Addr> {
+    let socket = interface::bind_std_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))?;
+    socket.connect((ip, 9)).with_context(|| format!("No route to {}", ip))?;
+
+    match socket.local_addr()?.ip() {
+        std::net::IpAddr::V4(source) => Ok(source),
+        std::net::IpAddr::V6(_) => bail!("No IPv4 source address for {}", ip),
+    }
+}
+
+fn set_recv_timeout(socket: &OwnedFd, timeout: Duration) -> Result<()> {
+    let timeval = libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    };
+
+    // SAFETY: timeval outlives the call and its size is passed alongside it.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &timeval as *const libc::timeval as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error()).context("Couldn't set raw socket timeout");
+    }
+
+    Ok(())
+}
+
+fn send_to(socket: &OwnedFd, segment: &[u8], ip: Ipv4Addr) -> Result<()> {
+    // SAFETY: sockaddr_in is plain data, so all-zeroes is a valid value.
+    let mut address: libc::sockaddr_in = unsafe { mem::zeroed() };
+    address.sin_family = libc::AF_INET as libc::sa_family_t;
+    address.sin_addr = libc::in_addr { s_addr: u32::from(ip).to_be() };
+
+    // SAFETY: segment and address outlive the call and their lengths are passed alongside them.
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            segment.as_ptr() as *const libc::c_void,
+            segment.len(),
+            0,
+            &address as *const libc::sockaddr_in as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+
+    if sent < 0 {
+        return Err(io::Error::last_os_error()).with_context(|| format!("Couldn't send probe to {}", ip));
+    }
+
+    Ok(())
+}
+
+fn recv(socket: &OwnedFd, buffer: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: buffer outlives the call and its length is passed alongside it.
+    let len = unsafe { libc::recv(socket.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+
+    if len < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(len as usize)
+    }
+}
+
+/// Builds a bare 20 byte TCP header. The kernel prepends the IP header since IP_HDRINCL is unset.
+fn tcp_segment(source: Ipv4Addr, destination: Ipv4Addr, source_port: u16, port: u16, sequence: u32, flags: u8) -> [u8; TCP_HEADER_LEN] {
+    let mut segment = [0u8; TCP_HEADER_LEN];
+    segment[0..2].copy_from_slice(&source_port.to_be_bytes());
+    segment[2..4].copy_from_slice(&port.to_be_bytes());
+    segment[4..8].copy_from_slice(&sequence.to_be_bytes());
+    segment[12] = ((TCP_HEADER_LEN / 4) as u8) << 4;
+    segment[13] = flags;
+    segment[14..16].copy_from_slice(&1024u16.to_be_bytes());
+
+    let checksum = tcp_checksum(source, destination, &segment);
+    segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+    segment
+}
+
+fn tcp_checksum(source: Ipv4Addr, destination: Ipv4Addr, segment: &[u8]) -> u16 {
+    let mut pseudo_header = Vec::with_capacity(12 + segment.len());
+    pseudo_header.extend_from_slice(&source.octets());
+    pseudo_header.extend_from_slice(&destination.octets());
+    pseudo_header.extend_from_slice(&[0, libc::IPPROTO_TCP as u8]);
+    pseudo_header.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    pseudo_header.extend_from_slice(segment);
+
+    let mut sum: u32 = pseudo_header
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Returns the remote port and TCP flags of a packet sent by `ip` to `source_port`.
+fn parse_reply(packet: &[u8], ip: Ipv4Addr, source_port: u16) -> Option<(u16, u8)> {
+    let header_len = usize::from(*packet.first()? & 0x0f) * 4;
+    let tcp = packet.get(header_len..header_len + TCP_HEADER_LEN)?;
+
+    if packet.get(12..16)? != ip.octets() || u16::from_be_bytes([tcp[2], tcp[3]]) != source_port {
+        return None;
+    }
+
+    Some((u16::from_be_bytes([tcp[0], tcp[1]]), tcp[13]))
+}
@@ -0,0 +1,425 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use futures::future::join_all;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::Rng;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use trust_dns_client::client::AsyncClient;
+use trust_dns_client::op::{Query, ResponseCode};
+use trust_dns_client::proto::xfer::{DnsHandle, DnsRequestOptions, FirstAnswer};
+use trust_dns_client::rr::{Name, RecordType};
+use trust_dns_client::tcp::TcpClientStream;
+use trust_dns_client::udp::UdpClientStream;
+
+use crate::interface::{InterfaceTcpStream, InterfaceUdpSocket};
+use crate::{query_record_type, QueryOptions, Resolution};
+
+/// Name every usable resolver must answer, overridable with `--validation-name`.
+pub const DEFAULT_VALIDATION_NAME: &str = "example.com";
+
+// Longest wait before a retry, however many attempts came before it.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+const DEFAULT_RESOLVER: SocketAddr = SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::new(8, 8, 8, 8)), 53);
+pub const DNS_PORT: u16 = 53;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// Where a failed query is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RetryStrategy {
+    /// Retry on the resolver that failed. Keeps each name on one resolver, which suits a flaky
+    /// network path or rate limiting that clears after a moment, but a resolver that is down
+    /// costs every retry.
+    Same,
+    /// Retry on the next resolver in the rotation. Routes around a broken or lagging resolver
+    /// and spreads retry load, at the cost of mixing answers from resolvers that may disagree.
+    Rotate,
+}
+
+/// How the pool picks the resolver for each query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResolverStrategy {
+    /// Take the resolvers in turn, each worker starting at its own offset.
+    RoundRobin,
+    /// Pick a resolver uniformly at random. Workers then don't move through the resolvers in
+    /// lockstep, so bursts are less likely to hit one resolver's rate limit together.
+    Random,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub strategy: RetryStrategy,
+    pub retries: usize,
+    /// Whether a SERVFAIL is retried like a timeout, or accepted as the answer.
+    pub servfail: bool,
+    /// Wait before the first retry, doubled for each one after it. Zero retries at once.
+    pub backoff: Duration,
+    /// Share of each wait that is randomized, from 0 (fixed) to 1 (anywhere from zero up to
+    /// the full wait).
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `attempt`, counting from 1: the backoff doubled per earlier
+    /// retry and capped at `MAX_RETRY_BACKOFF`, less a random part of up to `jitter` of it so
+    /// workers that failed together don't all retry together.
+    fn delay(&self, attempt: usize) -> Duration {
+        let doublings = u32::try_from(attempt.saturating_sub(1)).unwrap_or(u32::MAX).min(16);
+        let delay = self.backoff.saturating_mul(1 << doublings).min(MAX_RETRY_BACKOFF);
+
+        delay.mul_f64(1.0 - self.jitter * rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// Merges the `--dns-resolver` flags with the lines of `--resolvers-file`, dropping duplicates
+/// and falling back to 8.8.8.8:53 when neither names a resolver.
+pub fn collect(flags: Vec<SocketAddr>, file: Option<&str>) -> Result<Vec<SocketAddr>> {
+    let mut resolvers = flags;
+
+    if let Some(path) = file {
+        let from_file = load_file(path)?;
+        if from_file.is_empty() && resolvers.is_empty() {
+            bail!("No valid resolvers in {}", path);
+        }
+        resolvers.extend(from_file);
+    }
+
+    let mut seen = HashSet::new();
+    resolvers.retain(|resolver| seen.insert(*resolver));
+
+    if resolvers.is_empty() {
+        resolvers.push(DEFAULT_RESOLVER);
+    }
+
+    Ok(resolvers)
+}
+
+/// Reads one resolver per line as `ip:port`, `[ipv6]:port` or a bare address on port 53.
+/// Blank lines and `#` comments are skipped; anything else that doesn't parse is reported with
+/// its line number and skipped.
+fn load_file(path: &str) -> Result<Vec<SocketAddr>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Couldn't read resolvers file {}", path))?;
+    let mut resolvers = vec![];
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_resolver(line) {
+            Some(resolver) => resolvers.push(resolver),
+            None => warn!("Skipping invalid resolver {:?} on line {} of {}", line, number + 1, path),
+        }
+    }
+
+    info!("Loaded {} resolvers from {}", resolvers.len(), path);
+
+    Ok(resolvers)
+}
+
+fn parse_resolver(line: &str) -> Option<SocketAddr> {
+    if let Ok(resolver) = line.parse() {
+        return Some(resolver);
+    }
+
+    let ip: IpAddr = line.trim_start_matches('[').trim_end_matches(']').parse().ok()?;
+
+    Some(SocketAddr::new(ip, DNS_PORT))
+}
+
+/// Asks for the root zone's SOA, which every recursive resolver can answer from its hints. Any
+/// response counts, an error rcode included; only silence fails.
+async fn probe(mut client: AsyncClient, wait: Duration) -> Result<()> {
+    let query = client.lookup(Query::query(Name::root(), RecordType::SOA), DnsRequestOptions::default()).first_answer();
+
+    match tokio::time::timeout(wait, query).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(err.into()),
+        Err(_) => bail!("no answer within {:?}", wait),
+    }
+}
+
+/// Sends control queries to every resolver and keeps those that pass: `control_name` must
+/// resolve, and a random name under it must not, since a resolver that answers for names that
+/// don't exist (NXDOMAIN hijacking) would make every wordlist entry look live. Dropped resolvers
+/// are reported with the reason; it's an error if none pass.
+pub async fn validate(resolvers: Vec<SocketAddr>, transport: Transport, timeout: Duration, options: QueryOptions, control_name: &str) -> Result<Vec<SocketAddr>> {
+    let no_retries = RetryPolicy { strategy: RetryStrategy::Same, retries: 0, servfail: false, backoff: Duration::ZERO, jitter: 0.0 };
+    let checks = resolvers.into_iter().map(|resolver| async move {
+        let Ok(pool) = ResolverPool::connect(&[resolver], transport, timeout, no_retries, 0).await else {
+            return (resolver, Err("couldn't connect".to_string()));
+        };
+
+        let control = pool.resolve(control_name, options).await;
+        if control.addresses.is_empty() {
+            return (resolver, Err(format!("no address for {} ({})", control_name, control.rcode_name())));
+        }
+
+        let label = Alphanumeric.sample_string(&mut rand::thread_rng(), 16).to_lowercase();
+        let bogus = pool.resolve(&format!("{}.{}", label, control_name), options).await;
+        if !bogus.addresses.is_empty() {
+            return (resolver, Err(format!("answered a nonexistent name with {:?}", bogus.addresses)));
+        }
+
+        (resolver, Ok(()))
+    });
+
+    let mut valid = vec![];
+    for (resolver, check) in join_all(checks).await {
+        match check {
+            Ok(()) => valid.push(resolver),
+            Err(reason) => warn!("Dropping resolver {}: {}", resolver, reason),
+        }
+    }
+
+    if valid.is_empty() {
+        bail!("No resolver passed validation against {}", control_name);
+    }
+    info!("{} resolvers passed validation", valid.len());
+
+    Ok(valid)
+}
+
+/// Token bucket refilled at `rate` queries per second, holding up to one second of tokens.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        TokenBucket { rate, state: Mutex::new((rate.max(1.0), Instant::now())) }
+    }
+
+    async fn take(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().expect("token bucket lock poisoned");
+                let (tokens, refilled) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*refilled).as_secs_f64() * self.rate).min(self.rate.max(1.0));
+                *refilled = now;
+
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Independent query rate cap for each resolver, shared by every worker's pool so the cap holds
+/// for the whole scan rather than per worker.
+#[derive(Debug)]
+pub struct ResolverRateLimits {
+    buckets: HashMap<SocketAddr, TokenBucket>,
+}
+
+impl ResolverRateLimits {
+    pub fn new(resolvers: &[SocketAddr], per_second: f64) -> Self {
+        ResolverRateLimits {
+            buckets: resolvers.iter().map(|&resolver| (resolver, TokenBucket::new(per_second))).collect(),
+        }
+    }
+
+    async fn wait(&self, resolver: SocketAddr) {
+        if let Some(bucket) = self.buckets.get(&resolver) {
+            bucket.take().await;
+        }
+    }
+}
+
+/// One client per configured resolver, queried round-robin unless `with_strategy` says
+/// otherwise. Clients are cheap handles onto one
+/// connection, so concurrent lookups through the pool share it; over TCP they are pipelined.
+pub struct ResolverPool {
+    clients: Vec<(SocketAddr, AsyncClient)>,
+    next: AtomicUsize,
+    strategy: ResolverStrategy,
+    retry: RetryPolicy,
+    rate_limits: Option<Arc<ResolverRateLimits>>,
+    inflight: Option<Arc<Semaphore>>,
+}
+
+impl ResolverPool {
+    /// Connects to every resolver, starting the rotation at `offset` so workers don't all hit
+    /// the same resolver first. A resolver that can't be connected to is reported and left out
+    /// of the pool; it's an error only if none connects. UDP has no connection to fail, so each
+    /// UDP resolver is sent a root SOA query instead and counts as connected once it answers.
+    pub async fn connect(resolvers: &[SocketAddr], transport: Transport, timeout: Duration, retry: RetryPolicy, offset: usize) -> Result<Self> {
+        let mut pool = Self::reconnect(resolvers, transport, timeout, retry, offset).await?;
+        if transport == Transport::Udp {
+            let probes = pool.clients.iter().map(|(_, client)| probe(client.clone(), timeout));
+            let answered = join_all(probes).await;
+            let mut answered = answered.into_iter();
+            pool.clients.retain(|(resolver, _)| match answered.next().expect("one probe per client") {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Resolver {} didn't answer over {:?}, continuing without it: {}", resolver, transport, err);
+                    false
+                }
+            });
+            if pool.clients.is_empty() {
+                bail!("None of the resolvers {:?} answered", resolvers);
+            }
+            pool.next = AtomicUsize::new(offset % pool.clients.len());
+        }
+
+        Ok(pool)
+    }
+
+    /// `connect` without the UDP probe, for resolvers another pool has just reached.
+    pub async fn reconnect(resolvers: &[SocketAddr], transport: Transport, timeout: Duration, retry: RetryPolicy, offset: usize) -> Result<Self> {
+        let mut clients = vec![];
+
+        for &resolver in resolvers {
+            let connected = match transport {
+                Transport::Udp => {
+                    let stream = UdpClientStream::<InterfaceUdpSocket>::with_timeout(resolver, timeout);
+                    AsyncClient::connect(stream).await.map(|(client, bg)| {
+                        tokio::spawn(bg);
+                        client
+                    })
+                }
+                Transport::Tcp => {
+                    let (stream, sender) = TcpClientStream::<InterfaceTcpStream>::with_timeout(resolver, timeout);
+                    AsyncClient::with_timeout(stream, sender, timeout, None).await.map(|(client, bg)| {
+                        tokio::spawn(bg);
+                        client
+                    })
+                }
+            };
+
+            match connected {
+                Ok(client) => clients.push((resolver, client)),
+                Err(err) => warn!("Couldn't connect to resolver {} over {:?}, continuing without it: {}", resolver, transport, err),
+            }
+        }
+        if clients.is_empty() {
+            bail!("Couldn't connect to any of the resolvers {:?}", resolvers);
+        }
+
+        Ok(ResolverPool { next: AtomicUsize::new(offset % clients.len()), clients, strategy: ResolverStrategy::RoundRobin, retry, rate_limits: None, inflight: None })
+    }
+
+    /// The resolvers that connected, in rotation order.
+    pub fn resolvers(&self) -> Vec<SocketAddr> {
+        self.clients.iter().map(|(resolver, _)| *resolver).collect()
+    }
+
+    /// Picks the resolver of each query per `strategy`. Retries with `RetryStrategy::Rotate`
+    /// still move on to the resolver after the one picked.
+    pub fn with_strategy(mut self, strategy: ResolverStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Makes every query, retries included, wait for its resolver's token in `rate_limits`.
+    pub fn with_rate_limits(mut self, rate_limits: Option<Arc<ResolverRateLimits>>) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Holds a permit of `inflight` for every query, retries included, so pools sharing it never
+    /// have more queries outstanding than it has permits. Backoff and rate limit waits hold none.
+    pub fn with_inflight_limit(mut self, inflight: Option<Arc<Semaphore>>) -> Self {
+        self.inflight = inflight;
+        self
+    }
+
+    /// Resolves `hostname` on the next resolver in the rotation, or a random one. A timeout, and
+    /// a SERVFAIL unless the policy says otherwise, is retried per the retry policy before it is
+    /// accepted, since it often means one resolver failed rather than that the name has no records.
+    pub async fn resolve(&self, hostname: &str, options: QueryOptions) -> Resolution {
+        let len = self.clients.len();
+        let first = self.pick();
+
+        let mut resolution = Resolution::default();
+
+        for attempt in 0..=self.retry.retries {
+            let index = match self.retry.strategy {
+                RetryStrategy::Same => first,
+                RetryStrategy::Rotate => (first + attempt) % len,
+            };
+            let resolver = self.clients[index].0;
+
+            if attempt > 0 {
+                let delay = self.retry.delay(attempt);
+                if delay.is_zero() {
+                    info!("{} for {}, retrying with {}", resolution.rcode_name(), hostname, resolver);
+                } else {
+                    info!("{} for {}, retrying with {} in {:.1?}", resolution.rcode_name(), hostname, resolver, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+
+            resolution = self.query(index, hostname, options).await;
+
+            match resolution.rcode {
+                None => {}
+                Some(ResponseCode::ServFail) if self.retry.servfail => {}
+                Some(_) => break,
+            }
+        }
+
+        resolution
+    }
+
+    /// Resolves `hostname` on `count` different resolvers at once, the first picked as for
+    /// `resolve` and the rest following it in the rotation. Nothing is retried, since the point
+    /// is each resolver's own answer.
+    pub async fn resolve_each(&self, hostname: &str, options: QueryOptions, count: usize) -> Vec<Resolution> {
+        let len = self.clients.len();
+        let first = self.pick();
+
+        join_all((0..count.min(len)).map(|offset| self.query((first + offset) % len, hostname, options))).await
+    }
+
+    fn pick(&self) -> usize {
+        match self.strategy {
+            ResolverStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len(),
+            ResolverStrategy::Random => rand::thread_rng().gen_range(0..self.clients.len()),
+        }
+    }
+
+    /// One query on the resolver at `index`, after its rate limit token and an in-flight permit.
+    async fn query(&self, index: usize, hostname: &str, options: QueryOptions) -> Resolution {
+        let (resolver, client) = &self.clients[index];
+        let mut client = client.clone();
+
+        if let Some(rate_limits) = &self.rate_limits {
+            rate_limits.wait(*resolver).await;
+        }
+
+        let mut resolution = match &self.inflight {
+            Some(inflight) => {
+                let _permit = inflight.acquire().await.expect("in-flight semaphore closed");
+                query_record_type(&mut client, hostname, options.record_type, options).await
+            }
+            None => query_record_type(&mut client, hostname, options.record_type, options).await,
+        };
+        resolution.resolver = Some(*resolver);
+
+        resolution
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::{diff, RootDomain};
+
+/// When each subdomain of an earlier result was first seen, so a rerun that rewrites the
+/// result keeps that history in `first_seen` instead of starting over.
+#[derive(Debug, Default)]
+pub struct FirstSeen {
+    names: HashMap<String, DateTime<Utc>>,
+}
+
+impl FirstSeen {
+    /// Reads the earlier result at `path`. A missing file is a first run; an unreadable one is
+    /// reported and treated the same way.
+    pub fn load(path: &str) -> Self {
+        if !Path::new(path).exists() {
+            return FirstSeen::default();
+        }
+
+        let previous = match diff::load(path) {
+            Ok(previous) => previous,
+            Err(err) => {
+                warn!("Couldn't read earlier result {} for first-seen times, starting over: {:#}", path, err);
+                return FirstSeen::default();
+            }
+        };
+        // Results written without --timestamp-findings only know when their scan ran.
+        let scanned = previous.metadata.as_ref().map(|metadata| metadata.timestamp);
+        let names: HashMap<String, DateTime<Utc>> = previous
+            .subdomains
+            .into_iter()
+            .filter_map(|subdomain| Some((subdomain.name, subdomain.first_seen.or(scanned)?)))
+            .collect();
+        info!("Loaded first-seen times of {} subdomains from {}", names.len(), path);
+
+        FirstSeen { names }
+    }
+
+    /// Sets `first_seen` and `last_seen` on every subdomain of `root_domain`, found by a scan
+    /// that started at `seen`.
+    pub fn stamp(&self, root_domain: &mut RootDomain, seen: DateTime<Utc>) {
+        for subdomain in &mut root_domain.subdomains {
+            subdomain.first_seen = Some(self.names.get(&subdomain.name).copied().unwrap_or(seen));
+            subdomain.last_seen = Some(seen);
+        }
+    }
+
+    /// Subdomains of the earlier result that `root_domain` no longer has.
+    pub fn missing(&self, root_domain: &RootDomain) -> usize {
+        let found: HashSet<&str> = root_domain.subdomains.iter().map(|subdomain| subdomain.name.as_str()).collect();
+
+        self.names.keys().filter(|name| !found.contains(name.as_str())).count()
+    }
+}
@@ -0,0 +1,96 @@
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{fits_dns_limits, scan, RootDomain, ScanConfig};
+
+#[derive(Debug, Deserialize)]
+struct ScanRequest {
+    target: String,
+    #[serde(default)]
+    wordlist: Vec<String>,
+    ports: Option<Vec<u16>>,
+}
+
+/// How much one request may ask for, from the `serve` flags.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_wordlist: usize,
+    pub max_ports: usize,
+}
+
+struct Server {
+    config: ScanConfig,
+    limits: Limits,
+}
+
+/// Serves `POST /scan`, running each request with `config` as the defaults.
+pub async fn serve(listen: SocketAddr, config: ScanConfig, limits: Limits) -> Result<()> {
+    let app = Router::new()
+        .route("/scan", post(scan_handler))
+        .with_state(Arc::new(Server { config, limits }));
+
+    info!("Listening on {} (at most {} wordlist entries and {} ports per request)", listen, limits.max_wordlist, limits.max_ports);
+
+    axum::Server::bind(&listen)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+async fn scan_handler(
+    State(server): State<Arc<Server>>,
+    Json(request): Json<ScanRequest>,
+) -> Result<Json<RootDomain>, (StatusCode, String)> {
+    if let Err(reason) = check(&request, server.limits) {
+        warn!("Rejected scan request for {:?}: {}", request.target, reason);
+        return Err((StatusCode::BAD_REQUEST, reason));
+    }
+    info!("Scan request for {} ({} subdomains)", request.target, request.wordlist.len());
+
+    let mut config = server.config.clone();
+    if let Some(ports) = request.ports {
+        config.ports = ports;
+    }
+
+    scan(&config, &request.target, request.wordlist, false)
+        .await
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// Rejects what `scan` can't enumerate, such as an IP or network as the target, before any
+/// query goes out, and anything over `limits`.
+fn check(request: &ScanRequest, limits: Limits) -> Result<(), String> {
+    if request.target.parse::<IpAddr>().is_ok() || !is_domain(&request.target) {
+        return Err(format!("target must be a domain name, got {:?}", request.target));
+    }
+    if request.wordlist.len() > limits.max_wordlist {
+        return Err(format!("wordlist has {} entries, at most {} are allowed", request.wordlist.len(), limits.max_wordlist));
+    }
+    if let Some(ports) = &request.ports {
+        if ports.len() > limits.max_ports {
+            return Err(format!("{} ports requested, at most {} are allowed", ports.len(), limits.max_ports));
+        }
+        if ports.contains(&0) {
+            return Err("port 0 can't be scanned".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// A name within the DNS length limits whose labels are letters, digits, `-` and `_`, not
+/// starting or ending with `-`. A trailing dot is allowed.
+fn is_domain(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+
+    fits_dns_limits(name)
+        && name.split('.').all(|label| {
+            !label.starts_with('-') && !label.ends_with('-') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, Transaction};
+use tracing::info;
+
+use crate::{Address, RootDomain};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS domains (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS subdomains (
+    id INTEGER PRIMARY KEY,
+    domain_id INTEGER NOT NULL REFERENCES domains(id),
+    name TEXT NOT NULL UNIQUE,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+-- host is the domain itself or one of its subdomains; subdomain_id is NULL for the domain's
+-- own addresses.
+CREATE TABLE IF NOT EXISTS addresses (
+    id INTEGER PRIMARY KEY,
+    domain_id INTEGER NOT NULL REFERENCES domains(id),
+    subdomain_id INTEGER REFERENCES subdomains(id),
+    host TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    UNIQUE (host, ip)
+);
+CREATE TABLE IF NOT EXISTS ports (
+    address_id INTEGER NOT NULL REFERENCES addresses(id),
+    port INTEGER NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    PRIMARY KEY (address_id, port)
+);
+";
+
+/// Records a scan in the SQLite database at `path`, creating it and its tables if needed.
+/// Everything already in the database is upserted, so it only moves `last_seen` to `seen`
+/// while `first_seen` keeps the scan that found it first. All rows go in one transaction.
+pub fn write(path: &str, root_domain: &RootDomain, seen: DateTime<Utc>) -> Result<()> {
+    let mut connection = Connection::open(path).with_context(|| format!("Couldn't open SQLite database {}", path))?;
+    // SQLite leaves REFERENCES unchecked unless each connection asks.
+    connection.execute_batch("PRAGMA foreign_keys = ON").context("Couldn't enable SQLite foreign keys")?;
+    connection.execute_batch(SCHEMA).context("Couldn't create SQLite tables")?;
+    add_subdomain_ids(&connection)?;
+
+    let seen = seen.to_rfc3339();
+    let transaction = connection.transaction()?;
+    let domain_id: i64 = transaction.query_row(
+        "INSERT INTO domains (name, first_seen, last_seen) VALUES (?1, ?2, ?2)
+         ON CONFLICT (name) DO UPDATE SET last_seen = excluded.last_seen
+         RETURNING id",
+        params![root_domain.name, seen],
+        |row| row.get(0),
+    )?;
+
+    insert_addresses(&transaction, domain_id, None, &root_domain.name, &root_domain.addresses, &seen)?;
+    for subdomain in &root_domain.subdomains {
+        let subdomain_id: i64 = transaction.query_row(
+            "INSERT INTO subdomains (domain_id, name, first_seen, last_seen) VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT (name) DO UPDATE SET last_seen = excluded.last_seen
+             RETURNING id",
+            params![domain_id, subdomain.name, seen],
+            |row| row.get(0),
+        )?;
+        insert_addresses(&transaction, domain_id, Some(subdomain_id), &subdomain.name, &subdomain.addresses, &seen)?;
+    }
+
+    transaction.commit()?;
+    info!("Wrote {} subdomains to SQLite database {}", root_domain.subdomains.len(), path);
+
+    Ok(())
+}
+
+/// Databases written before addresses linked to their subdomain get the column, filled in from
+/// the host names.
+fn add_subdomain_ids(connection: &Connection) -> Result<()> {
+    let has_column: bool = connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('addresses') WHERE name = 'subdomain_id')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_column {
+        connection
+            .execute_batch(
+                "ALTER TABLE addresses ADD COLUMN subdomain_id INTEGER REFERENCES subdomains(id);
+                 UPDATE addresses SET subdomain_id = (SELECT id FROM subdomains WHERE subdomains.name = addresses.host);",
+            )
+            .context("Couldn't add subdomain_id to the SQLite addresses table")?;
+    }
+
+    Ok(())
+}
+
+/// `subdomain_id` is None for the domain's own addresses.
+fn insert_addresses(transaction: &Transaction, domain_id: i64, subdomain_id: Option<i64>, host: &str, addresses: &[Address], seen: &str) -> Result<()> {
+    for address in addresses {
+        let address_id: i64 = transaction.query_row(
+            "INSERT INTO addresses (domain_id, subdomain_id, host, ip, first_seen, last_seen) VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+             ON CONFLICT (host, ip) DO UPDATE SET last_seen = excluded.last_seen
+             RETURNING id",
+            params![domain_id, subdomain_id, host, address.ip.to_string(), seen],
+            |row| row.get(0),
+        )?;
+
+        for port in address.ports.iter().filter(|port| port.is_open()) {
+            transaction.execute(
+                "INSERT INTO ports (address_id, port, first_seen, last_seen) VALUES (?1, ?2, ?3, ?3)
+                 ON CONFLICT (address_id, port) DO UPDATE SET last_seen = excluded.last_seen",
+                params![address_id, port.port, seen],
+            )?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+
+// Queries time out long before this, so every sample fits.
+const MAX_LATENCY_MS: u64 = 60 * 60 * 1000;
+
+// Upper bounds of the latency histogram buckets in milliseconds. Fixed so histograms of
+// different scans line up; anything slower lands in a last, open-ended bucket.
+const BUCKET_BOUNDS_MS: [u64; 11] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+// Width of the bar of the fullest bucket when the histogram is printed.
+const BAR_WIDTH: u64 = 40;
+
+/// Per-query DNS latencies in milliseconds, recorded into an HDR histogram so huge scans don't
+/// keep every sample around.
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram {
+            histogram: Histogram::new_with_max(MAX_LATENCY_MS, 3).expect("Couldn't create latency histogram"),
+        }
+    }
+
+    pub fn record(&mut self, ms: u64) {
+        self.histogram.saturating_record(ms);
+    }
+
+    pub fn stats(&self) -> Option<LatencyStats> {
+        if self.histogram.is_empty() {
+            return None;
+        }
+
+        Some(LatencyStats {
+            min: self.histogram.min(),
+            p50: self.histogram.value_at_quantile(0.50),
+            p90: self.histogram.value_at_quantile(0.90),
+            p99: self.histogram.value_at_quantile(0.99),
+            max: self.histogram.max(),
+            samples: self.histogram.len(),
+            buckets: self.buckets(),
+        })
+    }
+
+    fn buckets(&self) -> Vec<LatencyBucket> {
+        let mut buckets: Vec<LatencyBucket> = BUCKET_BOUNDS_MS
+            .iter()
+            .map(|&le_ms| LatencyBucket { le_ms: Some(le_ms), count: 0 })
+            .chain(std::iter::once(LatencyBucket { le_ms: None, count: 0 }))
+            .collect();
+
+        for value in self.histogram.iter_recorded() {
+            let ms = self.histogram.highest_equivalent(value.value_iterated_to());
+            let index = BUCKET_BOUNDS_MS.iter().position(|&bound| ms <= bound).unwrap_or(BUCKET_BOUNDS_MS.len());
+            buckets[index].count += value.count_at_value();
+        }
+
+        buckets
+    }
+}
+
+/// Summary of per-query DNS latencies in milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub min: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub samples: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<LatencyBucket>,
+}
+
+impl LatencyStats {
+    /// The buckets as printable lines: the range, the count and a bar scaled to the fullest bucket.
+    pub fn histogram_lines(&self) -> Vec<String> {
+        let fullest = self.buckets.iter().map(|bucket| bucket.count).max().unwrap_or_default().max(1);
+        let mut lower = 0;
+
+        self.buckets
+            .iter()
+            .map(|bucket| {
+                let range = match bucket.le_ms {
+                    Some(upper) => format!("{}-{} ms", lower, upper),
+                    None => format!("> {} ms", lower),
+                };
+                lower = bucket.le_ms.unwrap_or(lower);
+                let bar = "#".repeat((bucket.count * BAR_WIDTH).div_ceil(fullest) as usize);
+
+                format!("{:>12} {:>8} {}", range, bucket.count, bar).trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+/// Answers that took at most `le_ms` milliseconds and more than the previous bucket's bound.
+/// `le_ms` is `None` for the last bucket, which has no upper bound.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
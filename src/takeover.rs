@@ -0,0 +1,84 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// CNAME suffixes of hosting services that hand out names on request, so a CNAME left pointing
+// at a deleted resource there can be claimed by anyone.
+const EMBEDDED_FINGERPRINTS: &[(&str, &str)] = &[
+    ("AWS S3", "s3.amazonaws.com"),
+    ("AWS S3", "s3-website.amazonaws.com"),
+    ("AWS Elastic Beanstalk", "elasticbeanstalk.com"),
+    ("Azure", "azurewebsites.net"),
+    ("Azure", "cloudapp.net"),
+    ("Azure", "cloudapp.azure.com"),
+    ("Azure", "trafficmanager.net"),
+    ("Azure", "blob.core.windows.net"),
+    ("Bitbucket", "bitbucket.io"),
+    ("Ghost", "ghost.io"),
+    ("GitHub Pages", "github.io"),
+    ("Heroku", "herokuapp.com"),
+    ("Heroku", "herokudns.com"),
+    ("Help Scout", "helpscoutdocs.com"),
+    ("Netlify", "netlify.app"),
+    ("Pantheon", "pantheonsite.io"),
+    ("Readme.io", "readme.io"),
+    ("Shopify", "myshopify.com"),
+    ("Surge.sh", "surge.sh"),
+    ("Unbounce", "unbouncepages.com"),
+    ("WordPress.com", "wordpress.com"),
+    ("Zendesk", "zendesk.com"),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fingerprint {
+    pub service: String,
+    pub cname: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fingerprints {
+    fingerprints: Vec<Fingerprint>,
+}
+
+impl Fingerprints {
+    /// Builds the embedded fingerprint set, extended by a JSON rules file of
+    /// `[{"service": "...", "cname": "..."}]` entries when one is given.
+    pub fn load(rules_file: Option<&str>) -> Result<Self> {
+        let mut fingerprints: Vec<Fingerprint> = EMBEDDED_FINGERPRINTS
+            .iter()
+            .map(|(service, cname)| Fingerprint {
+                service: service.to_string(),
+                cname: cname.to_string(),
+            })
+            .collect();
+
+        if let Some(rules_file) = rules_file {
+            let rules = fs::read_to_string(rules_file)
+                .with_context(|| format!("Couldn't read takeover rules file {}", rules_file))?;
+            let rules: Vec<Fingerprint> = serde_json::from_str(&rules)
+                .with_context(|| format!("Couldn't parse takeover rules file {}", rules_file))?;
+
+            fingerprints.extend(rules.into_iter().map(|rule| Fingerprint {
+                service: rule.service,
+                cname: rule.cname.trim_end_matches('.').to_lowercase(),
+            }));
+        }
+
+        Ok(Fingerprints { fingerprints })
+    }
+
+    /// Returns the service of the first fingerprint matched by any of the given CNAME targets.
+    pub fn check(&self, cnames: &[String]) -> Option<&str> {
+        cnames.iter().find_map(|cname| {
+            let cname = cname.trim_end_matches('.').to_lowercase();
+
+            self.fingerprints
+                .iter()
+                .find(|fingerprint| {
+                    cname == fingerprint.cname || cname.ends_with(&format!(".{}", fingerprint.cname))
+                })
+                .map(|fingerprint| fingerprint.service.as_str())
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::net::SocketAddr;
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
+
+/// A `host:port` from `--targets-file`, connect-scanned as is without enumerating anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Reads one target per line as `ip:port`, `[ipv6]:port` or `hostname:port`. Blank lines and
+/// `#` comments are skipped; anything else that doesn't parse is reported with its line number
+/// and skipped.
+pub fn load(path: &str) -> Result<Vec<Target>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Couldn't read targets file {}", path))?;
+    let mut targets = vec![];
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_target(line) {
+            Some(target) if !targets.contains(&target) => targets.push(target),
+            Some(_) => {}
+            None => warn!("Skipping invalid target {:?} on line {} of {}", line, number + 1, path),
+        }
+    }
+
+    if targets.is_empty() {
+        bail!("No valid targets in {}", path);
+    }
+    info!("Loaded {} targets from {}", targets.len(), path);
+
+    Ok(targets)
+}
+
+fn parse_target(line: &str) -> Option<Target> {
+    if let Ok(address) = line.parse::<SocketAddr>() {
+        return Some(Target { host: address.ip().to_string(), port: address.port() });
+    }
+
+    let (host, port) = line.rsplit_once(':')?;
+    if host.is_empty() || host.contains(':') {
+        return None;
+    }
+
+    Some(Target { host: host.trim_end_matches('.').to_lowercase(), port: port.parse().ok()? })
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::{self, Certificate, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use tracing::debug;
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+
+use crate::interface;
+
+pub const HTTPS_PORT: u16 = 443;
+
+// Where the common distributions keep their CA bundle, tried when SSL_CERT_FILE isn't set.
+const CA_BUNDLES: [&str; 3] = ["/etc/ssl/certs/ca-certificates.crt", "/etc/pki/tls/certs/ca-bundle.crt", "/etc/ssl/cert.pem"];
+
+/// Accepts every certificate. Names are read from whatever the server presents, so self-signed,
+/// expired or mismatched certificates are as useful here as valid ones.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// TLS client that completes the handshake whatever certificate the server presents.
+pub fn any_certificate_connector() -> TlsConnector {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+
+    TlsConnector::from(Arc::new(config))
+}
+
+/// TLS client that checks certificates against the system CA bundle, or the PEM file named by
+/// `SSL_CERT_FILE`. Used where credentials are sent, unlike the scan's own handshakes.
+pub fn verifying_connector() -> Result<TlsConnector> {
+    let path = match std::env::var("SSL_CERT_FILE") {
+        Ok(path) => path,
+        Err(_) => CA_BUNDLES
+            .into_iter()
+            .find(|path| std::path::Path::new(path).exists())
+            .context("No CA bundle found, set SSL_CERT_FILE to a PEM file of trusted certificates")?
+            .to_string(),
+    };
+    let pem = fs::read(&path).with_context(|| format!("Couldn't read CA bundle {}", path))?;
+    let certificates: Vec<Vec<u8>> = Pem::iter_from_buffer(&pem).filter_map(|pem| pem.ok()).map(|pem| pem.contents).collect();
+
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(&certificates);
+    if added == 0 {
+        bail!("No usable certificates in CA bundle {}", path);
+    }
+    debug!("Loaded {} CA certificates from {}", added, path);
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Reads the subject common name and DNS SAN entries of the certificates hosts serve on 443.
+pub struct CertificateCollector {
+    connector: TlsConnector,
+}
+
+/// Outcome of one handshake on 443.
+enum Handshake {
+    /// Names on the leaf certificate the server presented.
+    Certificate(Vec<String>),
+    /// The server ended the handshake, as SNI-routed frontends do for names they don't serve.
+    Refused,
+    /// No TCP connection, no answer in time, or a name that can't be sent as SNI.
+    Unreachable,
+}
+
+/// Found names sent as SNI to one address serving 443, with `--sni-vhosts`, grouped by how the
+/// server answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualHosts {
+    pub ip: IpAddr,
+    /// Names the presented certificate covers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted: Vec<String>,
+    /// Names the handshake completed for with a certificate that doesn't cover them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mismatched: Vec<String>,
+    /// Names the server refused the handshake for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<String>,
+}
+
+impl CertificateCollector {
+    pub fn new() -> Self {
+        CertificateCollector { connector: any_certificate_connector() }
+    }
+
+    /// Handshakes with `ip` on 443 using `hostname` as SNI and returns the names on the leaf
+    /// certificate, lowercased and deduplicated. Any failure yields no names.
+    pub async fn names(&self, ip: IpAddr, hostname: &str, connect_timeout: Duration, sockets: &Semaphore) -> Vec<String> {
+        match self.handshake(ip, hostname, connect_timeout, sockets).await {
+            Handshake::Certificate(names) => names,
+            Handshake::Refused | Handshake::Unreachable => vec![],
+        }
+    }
+
+    /// Handshakes with `ip` on 443 once per name in `hostnames` as SNI and sorts the names by
+    /// whether the certificate covers them. Names that got no answer are left out.
+    pub async fn virtual_hosts(&self, ip: IpAddr, hostnames: &[String], connect_timeout: Duration, sockets: &Semaphore) -> VirtualHosts {
+        let handshakes = join_all(hostnames.iter().map(|hostname| self.handshake(ip, hostname, connect_timeout, sockets))).await;
+        let mut virtual_hosts = VirtualHosts { ip, accepted: vec![], mismatched: vec![], rejected: vec![] };
+
+        for (hostname, handshake) in hostnames.iter().zip(handshakes) {
+            match handshake {
+                Handshake::Certificate(names) if covers(&names, hostname) => virtual_hosts.accepted.push(hostname.clone()),
+                Handshake::Certificate(_) => virtual_hosts.mismatched.push(hostname.clone()),
+                Handshake::Refused => virtual_hosts.rejected.push(hostname.clone()),
+                Handshake::Unreachable => {}
+            }
+        }
+
+        virtual_hosts
+    }
+
+    async fn handshake(&self, ip: IpAddr, hostname: &str, connect_timeout: Duration, sockets: &Semaphore) -> Handshake {
+        let Ok(server_name) = ServerName::try_from(hostname) else {
+            return Handshake::Unreachable;
+        };
+        let _permit = sockets.acquire().await.expect("socket semaphore closed");
+
+        let handshake = async {
+            let stream = interface::connect_tcp(SocketAddr::new(ip, HTTPS_PORT)).await.map_err(|err| (false, err))?;
+            self.connector.connect(server_name, stream).await.map_err(|err| (true, err))
+        };
+
+        let stream = match timeout(connect_timeout, handshake).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err((refused, err))) => {
+                debug!("TLS handshake with {} ({}) failed: {}", hostname, ip, err);
+                return if refused { Handshake::Refused } else { Handshake::Unreachable };
+            }
+            Err(_) => {
+                debug!("TLS handshake with {} ({}) timed out", hostname, ip);
+                return Handshake::Unreachable;
+            }
+        };
+
+        match stream.get_ref().1.peer_certificates().and_then(|chain| chain.first()) {
+            Some(certificate) => Handshake::Certificate(certificate_names(&certificate.0)),
+            None => Handshake::Certificate(vec![]),
+        }
+    }
+}
+
+/// Whether one of a certificate's names is `hostname`, or a wildcard covering its first label.
+fn covers(names: &[String], hostname: &str) -> bool {
+    let hostname = hostname.trim_end_matches('.').to_lowercase();
+
+    names.iter().any(|name| match name.strip_prefix("*.") {
+        Some(parent) => hostname.split_once('.').is_some_and(|(_, rest)| rest == parent),
+        None => *name == hostname,
+    })
+}
+
+fn certificate_names(der: &[u8]) -> Vec<String> {
+    let Ok((_, certificate)) = x509_parser::parse_x509_certificate(der) else {
+        return vec![];
+    };
+
+    let mut names: Vec<String> = certificate
+        .subject()
+        .iter_common_name()
+        .filter_map(|common_name| common_name.as_str().ok())
+        .map(str::to_string)
+        .collect();
+
+    if let Ok(Some(san)) = certificate.subject_alternative_name() {
+        names.extend(san.value.general_names.iter().filter_map(|name| match name {
+            GeneralName::DNSName(name) => Some(name.to_string()),
+            _ => None,
+        }));
+    }
+
+    let mut names: Vec<String> = names
+        .into_iter()
+        .map(|name| name.trim_end_matches('.').to_lowercase())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    names
+}
+
+/// Certificate names under `domain` that can be queued for enumeration. Wildcard entries such
+/// as `*.dev.example.com` contribute the name they cover, `dev.example.com`.
+pub fn candidates(names: &[String], domain: &str) -> Vec<String> {
+    let suffix = format!(".{}", domain);
+
+    names
+        .iter()
+        .map(|name| name.strip_prefix("*.").unwrap_or(name))
+        .filter(|name| name.ends_with(&suffix) && !name.contains('*'))
+        .map(str::to_string)
+        .collect()
+}
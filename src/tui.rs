@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indicatif::ProgressBar;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::pause::PauseSwitch;
+
+const RECENT_FINDINGS: usize = 100;
+const TICK: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ResolverStats {
+    queries: usize,
+    failures: usize,
+}
+
+#[derive(Debug, Default)]
+struct Activity {
+    recent: VecDeque<String>,
+    resolvers: BTreeMap<SocketAddr, ResolverStats>,
+}
+
+/// Live scan state drawn by the TUI. Progress comes from the same bar the plain output uses,
+/// which is just hidden while the TUI owns the terminal.
+pub struct Dashboard {
+    target: String,
+    progress: ProgressBar,
+    found: AtomicUsize,
+    activity: Mutex<Activity>,
+    finished: AtomicBool,
+}
+
+impl Dashboard {
+    pub fn new(target: &str, progress: ProgressBar) -> Self {
+        Dashboard {
+            target: target.to_string(),
+            progress,
+            found: AtomicUsize::new(0),
+            activity: Mutex::new(Activity::default()),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts one answered (or timed out) query against the resolver that handled it.
+    pub fn record_query(&self, resolver: Option<SocketAddr>, failed: bool) {
+        let Some(resolver) = resolver else {
+            return;
+        };
+        let mut activity = self.activity.lock().expect("dashboard lock poisoned");
+        let stats = activity.resolvers.entry(resolver).or_default();
+        stats.queries += 1;
+        stats.failures += usize::from(failed);
+    }
+
+    pub fn record_found(&self, name: &str, addresses: usize) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+
+        let mut activity = self.activity.lock().expect("dashboard lock poisoned");
+        activity.recent.push_front(format!("{} ({} addresses)", name, addresses));
+        activity.recent.truncate(RECENT_FINDINGS);
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Release);
+    }
+}
+
+/// Runs the TUI on a blocking thread until the scan finishes or the user quits. `p` toggles
+/// `pause`; `q`, Esc or Ctrl-C call `quit`, which should stop queueing names so the scan winds
+/// down and still writes its results.
+pub fn spawn(
+    dashboard: Arc<Dashboard>,
+    pause: Arc<PauseSwitch>,
+    quit: impl Fn() + Send + 'static,
+) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut terminal = match ratatui::try_init() {
+            Ok(terminal) => terminal,
+            Err(err) => {
+                eprintln!("Couldn't start the TUI: {}", err);
+                return;
+            }
+        };
+
+        run(&mut terminal, &dashboard, &pause, quit);
+        ratatui::restore();
+    })
+}
+
+fn run(terminal: &mut DefaultTerminal, dashboard: &Dashboard, pause: &PauseSwitch, quit: impl Fn()) {
+    let mut rate = Rate::new(dashboard.progress.position());
+    let mut quitting = false;
+
+    while !dashboard.finished.load(Ordering::Acquire) {
+        let throughput = rate.update(dashboard.progress.position());
+        let paused = *pause.subscribe().borrow();
+
+        if terminal.draw(|frame| draw(frame, dashboard, throughput, paused, quitting)).is_err() {
+            return;
+        }
+
+        if !event::poll(TICK).unwrap_or(false) {
+            continue;
+        }
+
+        if let Ok(Event::Key(key)) = event::read() {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('p') | KeyCode::Char(' ') => {
+                    pause.toggle();
+                }
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    quitting = true;
+                    quit();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
+                    quitting = true;
+                    quit();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Queries per second over the last tick.
+struct Rate {
+    at: Instant,
+    position: u64,
+    per_second: f64,
+}
+
+impl Rate {
+    fn new(position: u64) -> Self {
+        Rate { at: Instant::now(), position, per_second: 0.0 }
+    }
+
+    fn update(&mut self, position: u64) -> f64 {
+        let elapsed = self.at.elapsed().as_secs_f64();
+
+        if elapsed >= TICK.as_secs_f64() {
+            self.per_second = position.saturating_sub(self.position) as f64 / elapsed;
+            self.at = Instant::now();
+            self.position = position;
+        }
+
+        self.per_second
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard, throughput: f64, paused: bool, quitting: bool) {
+    let [gauge_area, stats_area, body_area, help_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [recent_area, resolvers_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body_area);
+
+    let position = dashboard.progress.position();
+    let length = dashboard.progress.length().unwrap_or(0).max(1);
+    let gauge = Gauge::default()
+        .block(Block::bordered().title(format!(" port-scanner: {} ", dashboard.target)))
+        .gauge_style(Style::default().fg(Color::Cyan))
+        .ratio((position as f64 / length as f64).min(1.0))
+        .label(format!("{}/{}", position, length));
+    frame.render_widget(gauge, gauge_area);
+
+    let state = if quitting {
+        "stopping".yellow()
+    } else if paused {
+        "paused".yellow()
+    } else {
+        "running".green()
+    };
+    let elapsed = dashboard.progress.elapsed().as_secs();
+    let stats = Line::from(vec![
+        format!(" {:02}:{:02}:{:02}  ", elapsed / 3600, elapsed / 60 % 60, elapsed % 60).into(),
+        format!("{:.0} q/s  ", throughput).into(),
+        format!("found {}  ", dashboard.found.load(Ordering::Relaxed)).bold(),
+        state,
+    ]);
+    frame.render_widget(Paragraph::new(stats), stats_area);
+
+    let activity = dashboard.activity.lock().expect("dashboard lock poisoned");
+
+    let recent = List::new(activity.recent.iter().map(String::as_str))
+        .block(Block::bordered().title(" Recent findings "));
+    frame.render_widget(recent, recent_area);
+
+    let rows = activity.resolvers.iter().map(|(resolver, stats)| {
+        Row::new(vec![resolver.to_string(), stats.queries.to_string(), stats.failures.to_string()])
+    });
+    let resolvers = Table::new(rows, [Constraint::Min(21), Constraint::Length(8), Constraint::Length(8)])
+        .header(Row::new(vec!["Resolver", "Queries", "Failed"]).bold())
+        .block(Block::bordered().title(" Resolvers "));
+    frame.render_widget(resolvers, resolvers_area);
+
+    frame.render_widget(Paragraph::new(" p pause/resume   q quit".dim()), help_area);
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{http, tls, Subdomain};
+
+// A post that takes longer than this counts as failed, and its findings go out with the next one.
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What one post to `--webhook` carries: the names found since the last successful post.
+#[derive(Serialize)]
+struct Batch<'a> {
+    target: &'a str,
+    subdomains: &'a VecDeque<Subdomain>,
+}
+
+/// Findings waiting for a post, oldest first.
+#[derive(Default)]
+struct Queue {
+    subdomains: VecDeque<Subdomain>,
+    /// Dropped to stay within `max_pending` since the last warning.
+    dropped: usize,
+}
+
+impl Queue {
+    fn trim(&mut self, max_pending: usize) {
+        while self.subdomains.len() > max_pending {
+            self.subdomains.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Posts findings to `--webhook` as JSON while the scan runs.
+pub struct Webhook {
+    url: Url,
+    authorization: Option<String>,
+    connector: Option<TlsConnector>,
+    pending: Mutex<Queue>,
+    max_pending: usize,
+}
+
+impl Webhook {
+    /// Checks `url` and `authorization` up front, so a typo fails at startup rather than on
+    /// every post. HTTPS certificates are verified, since the posts may carry credentials. At
+    /// most `max_pending` findings wait for a post; past that the oldest are dropped.
+    pub fn new(url: Url, authorization: Option<String>, max_pending: usize) -> Result<Self> {
+        let connector = match url.scheme() {
+            "https" => Some(tls::verifying_connector()?),
+            "http" => None,
+            scheme => bail!("Unsupported webhook scheme {}, use http or https", scheme),
+        };
+        if url.host_str().is_none() {
+            bail!("Webhook URL {} has no host", url);
+        }
+        if authorization.as_deref().is_some_and(|value| value.chars().any(char::is_control)) {
+            bail!("Webhook authorization header can't contain control characters");
+        }
+
+        Ok(Webhook { url, authorization, connector, pending: Mutex::new(Queue::default()), max_pending })
+    }
+
+    /// Queues a finding for the next post.
+    pub fn record(&self, subdomain: Subdomain) {
+        let mut pending = self.pending.lock().expect("webhook queue poisoned");
+        pending.subdomains.push_back(subdomain);
+        pending.trim(self.max_pending);
+    }
+
+    /// Posts the queued findings and drops them once the webhook accepted them. A failed post is
+    /// logged and its findings are queued again, ahead of any recorded meanwhile, for the next
+    /// one; the scan never waits on it.
+    pub async fn flush(&self, target: &str) {
+        let (subdomains, dropped) = {
+            let mut pending = self.pending.lock().expect("webhook queue poisoned");
+            (std::mem::take(&mut pending.subdomains), std::mem::take(&mut pending.dropped))
+        };
+        if dropped > 0 {
+            warn!("Dropped the {} oldest findings queued for {}, over --webhook-max-pending {}", dropped, self.url, self.max_pending);
+        }
+        if subdomains.is_empty() {
+            return;
+        }
+        let body = serde_json::to_vec(&Batch { target, subdomains: &subdomains }).expect("Couldn't serialize webhook batch");
+
+        let failure = match timeout(POST_TIMEOUT, self.post(&body)).await {
+            Ok(Ok(())) => {
+                debug!("Posted {} findings to {}", subdomains.len(), self.url);
+                return;
+            }
+            Ok(Err(err)) => format!("{:#}", err),
+            Err(_) => "timed out".to_string(),
+        };
+        warn!("Couldn't post {} findings to {}, retrying with the next post: {}", subdomains.len(), self.url, failure);
+
+        let mut pending = self.pending.lock().expect("webhook queue poisoned");
+        let recorded = std::mem::replace(&mut pending.subdomains, subdomains);
+        pending.subdomains.extend(recorded);
+        pending.trim(self.max_pending);
+    }
+
+    async fn post(&self, body: &[u8]) -> Result<()> {
+        // Bracketed for IPv6, which is how connect expects it too.
+        let host = self.url.host_str().context("Webhook URL has no host")?;
+        let port = self.url.port_or_known_default().context("Webhook URL has no port")?;
+        let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(authorization) = &self.authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let head = match &self.connector {
+            Some(connector) => {
+                let stream = connector.connect(http::server_name(&self.url)?, stream).await?;
+                http::send(stream, "POST", &self.url, &headers, body).await?
+            }
+            None => http::send(stream, "POST", &self.url, &headers, body).await?,
+        };
+        if !(200..300).contains(&head.status) {
+            bail!("webhook answered HTTP {}", head.status);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use tokio::sync::{OnceCell, Semaphore};
+use tracing::info;
+
+use crate::resolvers::ResolverPool;
+use crate::QueryOptions;
+
+const PROBE_LABEL_LEN: usize = 16;
+
+/// Answers a domain's wildcard record gave for names that do not exist. Each signature is the
+/// sorted address set of one probe, since some wildcards answer differently per label.
+#[derive(Debug, Default)]
+pub struct WildcardSet {
+    signatures: Vec<Vec<IpAddr>>,
+}
+
+impl WildcardSet {
+    pub fn signatures(&self) -> &[Vec<IpAddr>] {
+        &self.signatures
+    }
+
+    /// Returns the probe signature `addresses` is identical to, if any. Only an exact match counts
+    /// as wildcard noise, so real hosts that share some addresses with a wildcard are kept.
+    pub fn matches(&self, addresses: &[IpAddr]) -> Option<&[IpAddr]> {
+        let signature = signature(addresses.iter().copied());
+
+        self.signatures
+            .iter()
+            .find(|wildcard| **wildcard == signature)
+            .map(Vec::as_slice)
+    }
+}
+
+fn signature(addresses: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+    let signature: BTreeSet<IpAddr> = addresses.into_iter().collect();
+
+    signature.into_iter().collect()
+}
+
+/// Detects wildcard DNS by resolving random labels, caching the result per domain. The lock
+/// only guards the map; each domain is probed through its own cell, so workers asking about
+/// other domains never wait on those probes.
+#[derive(Debug)]
+pub struct WildcardDetector {
+    probes: usize,
+    cache: Mutex<HashMap<String, Arc<OnceCell<Arc<WildcardSet>>>>>,
+}
+
+impl WildcardDetector {
+    pub fn new(probes: usize) -> Self {
+        WildcardDetector { probes, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the wildcard addresses of `domain`, probing it the first time it's asked for.
+    /// Several probes are used because some wildcards rotate through a pool of addresses. Only
+    /// the caller that probes holds a `sockets` permit; the others just wait for its result.
+    pub async fn detect(&self, pool: &ResolverPool, domain: &str, options: QueryOptions, sockets: &Semaphore) -> Arc<WildcardSet> {
+        let cell = Arc::clone(self.cache.lock().expect("wildcard cache poisoned").entry(domain.to_string()).or_default());
+
+        Arc::clone(cell.get_or_init(|| self.probe(pool, domain, options, sockets)).await)
+    }
+
+    async fn probe(&self, pool: &ResolverPool, domain: &str, options: QueryOptions, sockets: &Semaphore) -> Arc<WildcardSet> {
+        let _permit = sockets.acquire().await.expect("socket semaphore closed");
+        let mut signatures = vec![];
+        for _ in 0..self.probes {
+            let label: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(PROBE_LABEL_LEN)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+
+            let addresses = pool.resolve(&format!("{}.{}", label, domain), options).await.addresses;
+            let signature = signature(addresses);
+
+            if !signature.is_empty() && !signatures.contains(&signature) {
+                signatures.push(signature);
+            }
+        }
+
+        if !signatures.is_empty() {
+            info!("Wildcard DNS detected for {}: {:?}", domain, signatures);
+        }
+
+        Arc::new(WildcardSet { signatures })
+    }
+
+    /// Returns the wildcards of the level `hostname` sits on when that level is below `apex`,
+    /// such as `*.api.example.com` for `x.api.example.com`, which probing the apex can't see.
+    /// Each level is probed once, the first time a name under it resolves.
+    pub async fn detect_level(&self, pool: &ResolverPool, hostname: &str, apex: &str, options: QueryOptions, sockets: &Semaphore) -> Option<Arc<WildcardSet>> {
+        let (_, level) = hostname.split_once('.')?;
+        if !level.ends_with(&format!(".{}", apex)) {
+            return None;
+        }
+
+        Some(self.detect(pool, level, options, sockets).await)
+    }
+}
@@ -0,0 +1,29 @@
+use std::process::{Command, Output};
+
+fn port_scanner(args: &[&str], target_env: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_port-scanner"));
+    command.args(args).env_remove("PORT_SCANNER_TARGET");
+    if let Some(target) = target_env {
+        command.env("PORT_SCANNER_TARGET", target);
+    }
+    command.output().expect("Couldn't run port-scanner")
+}
+
+#[test]
+fn reverse_ignores_target_from_env() {
+    let output = port_scanner(&["--list-ports", "reverse", "127.0.0.1/32"], Some("example.com"));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn scan_ignores_target_from_env() {
+    let output = port_scanner(&["--list-ports", "scan", "targets.txt"], Some("example.com"));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn reverse_rejects_target_on_command_line() {
+    let output = port_scanner(&["--list-ports", "-t", "example.com", "reverse", "127.0.0.1/32"], None);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not from --target"));
+}